@@ -3,9 +3,27 @@
 /// so they can be used directly from `main.rs`.
 pub mod prmpt;
 
-pub use prmpt::config::{load_config, Config, DEFAULT_CONFIG_KEY};
+pub use prmpt::config::{load_config, load_config_from, Config, InjectConfig, DEFAULT_CONFIG_KEY};
 // pub use prmpt::inject_code::inject; // Replaced by Injector
+pub use prmpt::git::GitOptions;
 pub use prmpt::inject_code::Injector; // Added
+pub use prmpt::json_response::parse_json_response;
+pub use prmpt::manifest::Manifest;
+pub use prmpt::parse_python::{
+    extract_python_signatures, extract_python_signatures_with_comments, extract_python_stub,
+    CommentKind,
+};
+pub use prmpt::placeholder::PlaceholderPolicy;
+pub use prmpt::policy::WritePolicy;
+pub use prmpt::report::{BlockAction, BlockReport, CommandOutcome, InjectionReport, ParseWarning};
+pub use prmpt::response::{
+    parse_response, parse_response_with_diagnostics, BlockOperation, ParseOptions, ParsedBlock,
+    ParsedResponse, PathMarker, ResponseFormat, SearchReplace,
+};
+pub use prmpt::review::{ReviewDecision, Reviewer};
+pub use prmpt::syntax::{check_syntax, SyntaxError};
+pub use prmpt::transcript::{read_transcript, select_message, TranscriptMessage};
+pub use prmpt::utils::{is_stdin, read_input};
 
 pub use prmpt::run::directory_peak;
 // pub use prmpt::run::run;             // Replaced by Generator
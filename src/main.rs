@@ -4,17 +4,25 @@ use log::LevelFilter;
 // Import all necessary functions/types from our library
 // These are re-exported at the crate root by src/lib.rs
 use prmpt::run_and_write; // Corrected path for the utility function
+use prmpt::{is_stdin, read_input, read_transcript, select_message};
 use prmpt::{
     load_config,
+    load_config_from,
+    CommentKind,
     Config,
     // inject, // Will use Injector::inject
     // run_and_write, // Will use the updated run_and_write that takes a Generator
-    Generator,          // Added
-    InjectOperation,    // Added
-    Injector,           // Added
+    Generator, // Added
+    GitOptions,
+    InjectOperation, // Added
+    InjectionReport,
+    Injector, // Added
+    Manifest,
+    PlaceholderPolicy,
+    ResponseFormat,
     DEFAULT_CONFIG_KEY, // Added import
 };
-use std::path::Path; // For Injector path arguments
+use std::path::{Path, PathBuf}; // For Injector path arguments
 
 /// A simple program to convert a code repository into an LLM prompt and inject code into a repository
 #[derive(Parser)]
@@ -60,6 +68,18 @@ struct GenerateArgs {
     #[arg(long)]
     docs_ignore: Vec<String>,
 
+    /// Kinds of Python comments kept with --docs-comments-only: header, block, marker (default: all)
+    #[arg(long, value_delimiter = ',')]
+    docs_comment_kinds: Vec<CommentKind>,
+
+    /// With --docs-comments-only, render Python files as `.pyi` stubs
+    #[arg(long)]
+    stubs: bool,
+
+    /// With --docs-comments-only, also write `.pyi` stubs of the Python files to this directory
+    #[arg(long)]
+    stubs_dir: Option<String>,
+
     /// Output file
     #[arg(short, long)]
     output: Option<String>,
@@ -83,18 +103,102 @@ struct GenerateArgs {
     /// Display outputs from Jupyter notebooks
     #[arg(long)]
     display_outputs: bool,
+
+    /// Write a manifest of file hashes next to the output for stale-edit detection
+    #[arg(long)]
+    manifest: bool,
 }
 
 /// Arguments for the `inject` subcommand
 #[derive(Args)]
 struct InjectArgs {
-    /// Path to the file containing code to inject
+    /// Path to the file containing code to inject (`-` reads stdin)
     #[arg(short, long, default_value = "prmpt.in")]
     input: String,
 
+    /// Chat transcript export (JSON with a messages array) to take the response from (`-` reads stdin)
+    #[arg(long, conflicts_with = "input")]
+    from_transcript: Option<String>,
+
+    /// Index of the transcript message to inject, counting from 0 (default: the last assistant message)
+    #[arg(long, requires = "from_transcript")]
+    message: Option<usize>,
+
     /// Path to the repository to inject the code into
     #[arg(short, long, default_value = ".")]
     path: String,
+
+    /// Code block delimiter used in the input file (defaults to the configured one, then "```")
+    #[arg(long)]
+    delimiter: Option<String>,
+
+    /// Format of the input: `markdown` (fenced blocks, directives, XML tags) or `json` file operations
+    #[arg(long, default_value = "markdown")]
+    format: ResponseFormat,
+
+    /// Manifest written by `generate --manifest`; files changed since then are merged, not overwritten
+    #[arg(long)]
+    manifest: Option<String>,
+
+    /// Overwrite files that changed since the manifest was written or have uncommitted changes
+    #[arg(long)]
+    force: bool,
+
+    /// Write blocks that contain placeholders such as `// ... rest unchanged` or shrink the file
+    #[arg(long)]
+    allow_placeholders: bool,
+
+    /// Fill unambiguous placeholders with the elided lines of the original file
+    #[arg(long)]
+    splice_placeholders: bool,
+
+    /// Write blocks whose content fails to parse instead of skipping them
+    #[arg(long)]
+    allow_syntax_errors: bool,
+
+    /// Run this command after injecting and roll back every touched file if it fails
+    #[arg(long)]
+    verify: Option<String>,
+
+    /// When verification fails, write a follow-up prompt with the failure to this file
+    #[arg(long, requires = "verify")]
+    followup: Option<String>,
+
+    /// Create this git branch and switch to it before injecting
+    #[arg(long)]
+    git_branch: Option<String>,
+
+    /// Check out the new branch in a git worktree at this path and inject there
+    #[arg(long, requires = "git_branch")]
+    git_worktree: Option<String>,
+
+    /// Commit exactly the touched files with this message after injecting
+    #[arg(long)]
+    commit: Option<String>,
+
+    /// Review each block and its diff before applying it, answering on stdin
+    #[arg(long)]
+    interactive: bool,
+
+    /// Only inject blocks whose path matches this glob (repeatable)
+    #[arg(long)]
+    only: Vec<String>,
+
+    /// Skip blocks whose path matches this glob (repeatable)
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// Print the injection report as JSON instead of a summary table
+    #[arg(long)]
+    json: bool,
+
+    /// Exit with a non-zero status when any block was skipped
+    #[arg(long)]
+    strict: bool,
+
+    /// Only accept explicit path and directive markers instead of guessing paths from prose
+    #[arg(long)]
+    strict_paths: bool,
 }
 
 // Define reserved keywords for subcommands to avoid conflict with config names if needed
@@ -127,8 +231,14 @@ fn main() {
                 language: args.language,
                 docs_comments_only: Some(args.docs_comments_only),
                 docs_ignore: Some(args.docs_ignore),
+                docs_comment_kinds: (!args.docs_comment_kinds.is_empty())
+                    .then_some(args.docs_comment_kinds),
+                docs_stubs: Some(args.stubs),
+                stubs_dir: args.stubs_dir,
                 use_gitignore: Some(args.use_gitignore),
                 display_outputs: Some(args.display_outputs),
+                manifest: Some(args.manifest),
+                inject: None,
                 prompts: None, // Prompts are usually part of prmpt.yaml, not direct CLI flags here.
             };
             let generator = Generator;
@@ -138,9 +248,85 @@ fn main() {
             }
         }
         Some(Commands::Inject(args)) => {
-            let injector = Injector;
-            if let Err(e) = injector.inject(Path::new(&args.input), Path::new(&args.path)) {
-                eprintln!("Error injecting code: {e:?}"); // Use {:?} for anyhow::Error
+            // Injection settings such as hooks come from the target repository's base
+            // configuration, as does the delimiter its prompts are generated with
+            let (config_delimiter, inject_config) = match load_config_from(Path::new(&args.path)) {
+                Ok(mut configs) => {
+                    let base = configs.remove(DEFAULT_CONFIG_KEY);
+                    let delimiter = base.as_ref().and_then(|config| config.delimiter.clone());
+                    (
+                        delimiter,
+                        base.and_then(|config| config.inject).unwrap_or_default(),
+                    )
+                }
+                Err(e) => {
+                    eprintln!("Failed to load configuration: {e}");
+                    std::process::exit(1);
+                }
+            };
+            let manifest = match args.manifest.as_deref().map(Path::new).map(Manifest::load) {
+                Some(Ok(manifest)) => Some(manifest),
+                Some(Err(e)) => {
+                    eprintln!("Error loading manifest: {e:?}");
+                    std::process::exit(1);
+                }
+                None => None,
+            };
+            let injector = Injector {
+                delimiter: args.delimiter.or(config_delimiter),
+                hooks: inject_config.hooks,
+                manifest,
+                force: args.force,
+                only: args.only,
+                exclude: args.exclude,
+                interactive: args.interactive,
+                strict_paths: args.strict_paths,
+                format: args.format,
+                allow_syntax_errors: args.allow_syntax_errors,
+                policy: inject_config.policy.unwrap_or_default(),
+                verify: args.verify,
+                followup: args.followup.map(PathBuf::from),
+                placeholders: PlaceholderPolicy {
+                    patterns: inject_config.placeholder_patterns,
+                    max_shrink_percent: inject_config.max_shrink_percent,
+                    allow: args.allow_placeholders,
+                    splice: args.splice_placeholders,
+                },
+                git: (args.git_branch.is_some() || args.commit.is_some()).then(|| GitOptions {
+                    branch: args.git_branch,
+                    worktree: args.git_worktree.map(PathBuf::from),
+                    commit_message: args.commit,
+                }),
+            };
+            let result = match &args.from_transcript {
+                Some(transcript) => {
+                    inject_from_transcript(&injector, transcript, args.message, &args.path)
+                }
+                None => injector.inject(Path::new(&args.input), Path::new(&args.path)),
+            };
+            let report = match result {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error injecting code: {e:?}"); // Use {:?} for anyhow::Error
+                    std::process::exit(1);
+                }
+            };
+            if args.json {
+                match serde_json::to_string_pretty(&report) {
+                    Ok(json) => println!("{json}"),
+                    Err(e) => {
+                        eprintln!("Error serializing injection report: {e:?}");
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                println!("{report}");
+            }
+            let verify_failed = report
+                .verification
+                .as_ref()
+                .is_some_and(|verification| !verification.success());
+            if verify_failed || (args.strict && report.has_skipped()) {
                 std::process::exit(1);
             }
         }
@@ -185,3 +371,21 @@ fn main() {
 }
 
 // Old InjectCli and GenerateCli structs are removed as their fields are now in InjectArgs and GenerateArgs.
+
+/// Injects a message taken from a chat transcript export.
+fn inject_from_transcript(
+    injector: &Injector,
+    transcript: &str,
+    message: Option<usize>,
+    repo_path: &str,
+) -> anyhow::Result<InjectionReport> {
+    let transcript = Path::new(transcript);
+    if injector.interactive && is_stdin(transcript) {
+        anyhow::bail!(
+            "Interactive review reads answers from stdin, so the transcript must come from a file"
+        );
+    }
+    let messages = read_transcript(&read_input(transcript)?)?;
+    let response = select_message(&messages, message)?;
+    injector.inject_response(response, Path::new(repo_path))
+}
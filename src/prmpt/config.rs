@@ -1,7 +1,13 @@
 //! Holds the configuration structure (`Config`) and functionality to load configurations.
 
+use crate::prmpt::parse_python::CommentKind;
+use crate::prmpt::policy::WritePolicy;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
 
 /// Configuration structure that holds various options for generating or injecting code.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,10 +28,39 @@ pub struct Config {
     pub docs_comments_only: Option<bool>,
     /// Patterns to ignore specifically in documentation comments.
     pub docs_ignore: Option<Vec<String>>,
+    /// Kinds of Python `#` comments kept in docs-only output (all when unset).
+    pub docs_comment_kinds: Option<Vec<CommentKind>>,
+    /// If true, Python docs-only output is rendered as `.pyi` stubs.
+    pub docs_stubs: Option<bool>,
+    /// Directory that `run_and_write` also writes `.pyi` stubs of the Python files to,
+    /// mirroring the repository layout (docs-only mode). Like `output`, it is relative
+    /// to the working directory, and it is skipped when it lies inside the repository.
+    pub stubs_dir: Option<String>,
     /// If true, respects patterns in a `.gitignore` file.
     pub use_gitignore: Option<bool>,
     /// If true, any outputs from Jupyter Notebook cells will be included in the generated prompt.
     pub display_outputs: Option<bool>,
+    /// If true, a manifest of SHA-256 hashes of the emitted files is written next to
+    /// the output file (`<output>.manifest.json`) for stale-edit detection on inject.
+    pub manifest: Option<bool>,
+    /// Settings for the `inject` subcommand.
+    pub inject: Option<InjectConfig>,
+}
+
+/// Settings used when injecting code back into the repository.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InjectConfig {
+    /// Commands run on every injected file matching a glob, e.g. `"*.rs": "rustfmt {file}"`.
+    /// `{file}` is replaced with the repository-relative path of the injected file.
+    pub hooks: Option<BTreeMap<String, String>>,
+    /// Regexes for lines that mark elided code, e.g. `// ... rest unchanged`.
+    /// Replaces the built-in patterns when set.
+    pub placeholder_patterns: Option<Vec<String>>,
+    /// Refuse blocks that are more than this many percent shorter than the file
+    /// they replace (default 50).
+    pub max_shrink_percent: Option<u32>,
+    /// Limits on what injected blocks may touch, see [`WritePolicy`].
+    pub policy: Option<WritePolicy>,
 }
 
 pub const DEFAULT_CONFIG_KEY: &str = "base";
@@ -41,8 +76,13 @@ fn create_default_base_config() -> Config {
         prompts: None,
         docs_comments_only: None,
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(true),
         display_outputs: None,
+        manifest: None,
+        inject: None,
     }
 }
 
@@ -50,7 +90,13 @@ fn create_default_base_config() -> Config {
 /// The file can contain a single configuration or multiple named configurations.
 /// If no file exists or no 'base' config is found, returns a default 'base' config.
 pub fn load_config() -> Result<HashMap<String, Config>, Box<dyn std::error::Error>> {
-    let config_path = Path::new("prmpt.yaml");
+    load_config_from(Path::new(""))
+}
+
+/// Like [`load_config`], but reads the `prmpt.yaml` in `dir`, e.g. the repository
+/// `prmpt inject` writes into.
+pub fn load_config_from(dir: &Path) -> Result<HashMap<String, Config>, Box<dyn std::error::Error>> {
+    let config_path = &dir.join("prmpt.yaml");
 
     // If the config file doesn't exist, return default base config
     if !config_path.exists() {
@@ -77,8 +123,13 @@ pub fn load_config() -> Result<HashMap<String, Config>, Box<dyn std::error::Erro
         "prompts",
         "docs_comments_only",
         "docs_ignore",
+        "docs_comment_kinds",
+        "docs_stubs",
+        "stubs_dir",
         "use_gitignore",
         "display_outputs",
+        "manifest",
+        "inject",
     ];
 
     // Check if this is a mixed structure (top-level config fields + nested configs)
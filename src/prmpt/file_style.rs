@@ -0,0 +1,100 @@
+//! Detects and re-applies the textual conventions of an existing file (line endings,
+//! byte order mark and trailing newline), so that injected content keeps the style of
+//! the file it replaces and an unchanged round trip is a byte-for-byte no-op.
+
+const BOM: char = '\u{FEFF}';
+
+/// Line-ending convention of a text file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Unix style `\n` only.
+    Lf,
+    /// Windows style `\r\n` only.
+    CrLf,
+    /// Both styles occur; content is written back with its own endings untouched.
+    Mixed,
+}
+
+/// Textual conventions of a file that injection should preserve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStyle {
+    pub line_ending: LineEnding,
+    /// Whether the file starts with a UTF-8 byte order mark.
+    pub bom: bool,
+    /// Whether the file ends with a line break.
+    pub trailing_newline: bool,
+}
+
+impl Default for FileStyle {
+    /// The style used for newly created files: LF endings, no BOM, final newline.
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            bom: false,
+            trailing_newline: true,
+        }
+    }
+}
+
+impl FileStyle {
+    /// Detects the conventions used by `contents`. Empty files carry no convention
+    /// and yield the default style.
+    pub fn detect(contents: &str) -> Self {
+        let body = contents.strip_prefix(BOM).unwrap_or(contents);
+        if body.is_empty() {
+            return Self {
+                bom: contents.starts_with(BOM),
+                ..Self::default()
+            };
+        }
+
+        let crlf_count = body.matches("\r\n").count();
+        let lone_lf_count = body.matches('\n').count() - crlf_count;
+        let line_ending = match (crlf_count, lone_lf_count) {
+            (0, _) => LineEnding::Lf,
+            (_, 0) => LineEnding::CrLf,
+            _ => LineEnding::Mixed,
+        };
+
+        Self {
+            line_ending,
+            bom: contents.starts_with(BOM),
+            trailing_newline: body.ends_with('\n'),
+        }
+    }
+
+    /// Rewrites `content` so it follows this style.
+    pub fn apply(&self, content: &str) -> String {
+        let mut body = content.strip_prefix(BOM).unwrap_or(content).to_string();
+
+        let newline = match self.line_ending {
+            LineEnding::Lf => {
+                body = body.replace("\r\n", "\n");
+                "\n"
+            }
+            LineEnding::CrLf => {
+                body = body.replace("\r\n", "\n").replace('\n', "\r\n");
+                "\r\n"
+            }
+            LineEnding::Mixed => "\n",
+        };
+
+        if self.trailing_newline {
+            if !body.is_empty() && !body.ends_with('\n') {
+                body.push_str(newline);
+            }
+        } else {
+            while body.ends_with('\n') {
+                body.pop();
+                if body.ends_with('\r') {
+                    body.pop();
+                }
+            }
+        }
+
+        if self.bom {
+            body.insert(0, BOM);
+        }
+        body
+    }
+}
@@ -0,0 +1,154 @@
+//! Git integration for injection: protects files with uncommitted changes, optionally
+//! moves to a new branch or worktree first, and commits exactly the touched paths so
+//! every model response becomes one reviewable commit. Uses the `git` executable.
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// What the `Injector` should do with git around an injection.
+#[derive(Debug, Clone, Default)]
+pub struct GitOptions {
+    /// Create this branch and switch to it (or check it out in `worktree`) before injecting.
+    pub branch: Option<String>,
+    /// Check out `branch` in a new worktree at this path and inject there instead.
+    pub worktree: Option<PathBuf>,
+    /// Commit the touched paths with this message after injecting.
+    pub commit_message: Option<String>,
+}
+
+/// Runs `git` in `dir` and returns its trimmed stdout, failing with its stderr.
+fn git<I, S>(dir: &Path, args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<S> = args.into_iter().collect();
+    let display_args: Vec<_> = args
+        .iter()
+        .map(|a| a.as_ref().to_string_lossy().into_owned())
+        .collect();
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(&args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            display_args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+/// Canonical root of the git work tree containing `dir`.
+pub fn toplevel(dir: &Path) -> Result<PathBuf> {
+    let root = git(dir, ["rev-parse", "--show-toplevel"])
+        .with_context(|| format!("{dir:?} is not inside a git repository"))?;
+    fs::canonicalize(&root).with_context(|| format!("Failed to canonicalize git root: {root:?}"))
+}
+
+/// Absolute paths of every file with uncommitted changes (staged, unstaged or
+/// untracked) in the work tree rooted at `root`.
+pub fn dirty_paths(root: &Path) -> Result<HashSet<PathBuf>> {
+    let status = git(
+        root,
+        ["status", "--porcelain=v1", "-z", "--untracked-files=all"],
+    )?;
+    let mut paths = HashSet::new();
+    let mut entries = status.split('\0').filter(|e| !e.is_empty());
+    while let Some(entry) = entries.next() {
+        let (code, path) = entry.split_at(3.min(entry.len()));
+        paths.insert(root.join(path));
+        // Renames and copies are followed by their original path
+        if code.contains('R') || code.contains('C') {
+            if let Some(original) = entries.next() {
+                paths.insert(root.join(original));
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Creates `branch` at the current commit and switches to it, keeping the work tree.
+pub fn create_branch(root: &Path, branch: &str) -> Result<()> {
+    git(root, ["switch", "-c", branch])?;
+    info!("Switched to new branch '{branch}'");
+    Ok(())
+}
+
+/// Adds a worktree at `path` with `branch` created at the current commit.
+pub fn add_worktree(root: &Path, path: &Path, branch: &str) -> Result<()> {
+    git(
+        root,
+        [
+            OsStr::new("worktree"),
+            OsStr::new("add"),
+            OsStr::new("-b"),
+            OsStr::new(branch),
+            path.as_os_str(),
+        ],
+    )?;
+    info!("Added worktree {path:?} on new branch '{branch}'");
+    Ok(())
+}
+
+/// Stages exactly `paths` (including deletions) and commits only them, leaving
+/// anything else in the index alone. Returns the new commit hash, or `None` when
+/// there was nothing to commit.
+pub fn commit_paths(root: &Path, paths: &[PathBuf], message: &str) -> Result<Option<String>> {
+    if paths.is_empty() {
+        info!("No files were touched. Nothing to commit.");
+        return Ok(None);
+    }
+    let pathspecs = paths.iter().map(|p| p.as_os_str());
+    let mut add_args = vec![OsStr::new("add"), OsStr::new("-A"), OsStr::new("--")];
+    add_args.extend(pathspecs.clone());
+    git(root, add_args)?;
+
+    let mut diff_args = vec![
+        OsStr::new("diff"),
+        OsStr::new("--cached"),
+        OsStr::new("--quiet"),
+        OsStr::new("--"),
+    ];
+    diff_args.extend(pathspecs.clone());
+    // `diff --quiet` exits with 1 when there are differences
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(diff_args)
+        .status()
+        .context("Failed to run git")?;
+    match status.code() {
+        Some(0) => {
+            info!("Touched files have no changes. Nothing to commit.");
+            return Ok(None);
+        }
+        Some(1) => {}
+        _ => bail!("`git diff --cached --quiet` failed with {status}"),
+    }
+
+    let mut commit_args = vec![
+        OsStr::new("commit"),
+        OsStr::new("-m"),
+        OsStr::new(message),
+        OsStr::new("--"),
+    ];
+    commit_args.extend(pathspecs);
+    git(root, commit_args)?;
+    let hash = git(root, ["rev-parse", "HEAD"])?;
+    info!("Committed {} file(s) as {hash}", paths.len());
+    Ok(Some(hash))
+}
@@ -0,0 +1,87 @@
+//! Runs post-inject hooks: commands configured per glob (e.g. formatters) that are
+//! executed on every file written by the `Injector`.
+
+use crate::prmpt::report::CommandOutcome;
+use log::{info, warn};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Placeholder replaced with the repository-relative path of the injected file.
+const FILE_PLACEHOLDER: &str = "{file}";
+
+/// Runs every hook whose glob matches one of `files` (paths relative to `repo_root`).
+/// Commands are split on whitespace and executed directly, without a shell, from the
+/// repository root, so file names coming from model output are never interpreted.
+/// Hooks run in glob order; a file matching several globs gets each command.
+pub fn run_hooks(
+    hooks: &BTreeMap<String, String>,
+    files: &[PathBuf],
+    repo_root: &Path,
+) -> Vec<CommandOutcome> {
+    let patterns: Vec<(glob::Pattern, &String)> = hooks
+        .iter()
+        .filter_map(|(glob_str, command)| match glob::Pattern::new(glob_str) {
+            Ok(pattern) => Some((pattern, command)),
+            Err(e) => {
+                warn!("Invalid hook glob '{glob_str}': {e}");
+                None
+            }
+        })
+        .collect();
+
+    let mut outcomes = Vec::new();
+    for file in files {
+        for (pattern, command) in &patterns {
+            if pattern.matches_path(file) {
+                outcomes.push(run_hook(command, file, repo_root));
+            }
+        }
+    }
+    outcomes
+}
+
+/// Runs a single hook command for `file` and captures its output.
+fn run_hook(command: &str, file: &Path, repo_root: &Path) -> CommandOutcome {
+    let file_str = file.to_string_lossy();
+    let args: Vec<String> = command
+        .split_whitespace()
+        .map(|arg| arg.replace(FILE_PLACEHOLDER, &file_str))
+        .collect();
+    let display_command = args.join(" ");
+
+    let mut outcome = CommandOutcome::new(display_command.clone(), Some(file.to_path_buf()));
+
+    let Some((program, program_args)) = args.split_first() else {
+        outcome.stderr = "Empty hook command".to_string();
+        warn!("Empty hook command configured for {file:?}");
+        return outcome;
+    };
+
+    info!("Running hook `{display_command}`");
+    match Command::new(program)
+        .args(program_args)
+        .current_dir(repo_root)
+        .output()
+    {
+        Ok(output) => {
+            outcome.exit_code = output.status.code();
+            outcome.stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+            outcome.stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            if !outcome.success() {
+                warn!(
+                    "Hook `{display_command}` failed with status {:?}: {}",
+                    outcome.exit_code,
+                    outcome.stderr.trim_end()
+                );
+            }
+        }
+        Err(e) => {
+            outcome.stderr = e.to_string();
+            warn!("Failed to run hook `{display_command}`: {e}");
+        }
+    }
+    outcome
+}
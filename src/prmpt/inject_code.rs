@@ -1,135 +1,196 @@
 //! Provides functionality for injecting code from a generated file (e.g., "prmpt.out")
 //! back into the repository at specified file paths.
 
+use crate::prmpt::file_style::FileStyle;
+use crate::prmpt::git::{self, GitOptions};
+use crate::prmpt::hooks::run_hooks;
+use crate::prmpt::json_response::parse_json_response;
+use crate::prmpt::manifest::{sha256_hex, Manifest, ManifestEntry};
+use crate::prmpt::merge::merge_texts;
+use crate::prmpt::notebook::{is_cell_render, is_notebook, update_notebook};
+use crate::prmpt::path_resolution::{validate_target, PathResolver, ResolvedPath};
+use crate::prmpt::placeholder::{splice, LazyOutput, PlaceholderDetector, PlaceholderPolicy};
+use crate::prmpt::policy::{is_binary, PolicyChecker, WritePolicy};
+use crate::prmpt::report::{BlockAction, BlockReport, CommandOutcome, InjectionReport};
+use crate::prmpt::response::{
+    parse_response_with_diagnostics, BlockOperation, ParseOptions, ParsedBlock, ResponseFormat,
+    SearchReplace,
+};
+use crate::prmpt::review::{ReviewDecision, Reviewer};
+use crate::prmpt::symbols::{replace_symbol, SymbolLanguage};
+use crate::prmpt::syntax::check_syntax;
 use crate::prmpt::traits::InjectOperation; // Import the trait
-use anyhow::{Context, Error, Result};
+use crate::prmpt::utils::{compile_globs, is_stdin, read_input};
+use crate::prmpt::verify::{run_verify, write_followup_prompt, Backup};
+use anyhow::{bail, Context, Error, Result};
 use log::{error, info, warn};
 use rand::distr::Alphanumeric;
 use rand::rngs::ThreadRng;
 use rand::Rng;
 use std::{
-    fs,
+    collections::{BTreeMap, HashSet},
+    fs, io,
     path::{Path, PathBuf},
 }; // For the Result type & context
 
-/// Parser states for processing injection file content
-#[derive(Debug, PartialEq)]
-enum ParserState {
-    ExpectingPath,
-    InCodeBlock,
-}
-
-/// Represents a parsed code block with its target file path
+/// A parsed block on its way into the repository
 #[derive(Debug)]
 struct CodeBlock {
     target_path: String,
+    /// The path as written in the input, when resolution changed it
+    original_path: Option<String>,
+    /// Function, class or impl item to replace instead of the whole file
+    symbol: Option<String>,
     content: String,
+    operation: BlockOperation,
+    /// 1-based line range of the block in the input
+    start_line: usize,
+    end_line: usize,
 }
 
-/// Struct for implementing the InjectOperation trait.
-#[derive(Default)]
-pub struct Injector;
-
-/// Parser for processing injection file content
-struct InjectionParser {
-    state: ParserState,
-    current_target_path: Option<String>,
-    current_code_block: String,
-    blocks: Vec<CodeBlock>,
-}
-
-impl InjectionParser {
-    fn new() -> Self {
+impl From<ParsedBlock> for CodeBlock {
+    fn from(block: ParsedBlock) -> Self {
         Self {
-            state: ParserState::ExpectingPath,
-            current_target_path: None,
-            current_code_block: String::new(),
-            blocks: Vec::new(),
+            target_path: block.target_path,
+            original_path: None,
+            symbol: block.symbol,
+            content: block.content,
+            operation: block.operation,
+            start_line: block.start_line,
+            end_line: block.end_line,
         }
     }
+}
 
-    fn parse(mut self, content: &str) -> Vec<CodeBlock> {
-        let delimiter = "```";
-
-        for line in content.lines() {
-            match self.state {
-                ParserState::ExpectingPath => {
-                    if line.trim_start().starts_with(delimiter) {
-                        // Handle optional path on the same line as the opening code fence
-                        if let Some(path_on_fence) = extract_path_from_fence(line, delimiter) {
-                            self.current_target_path = Some(path_on_fence.to_string());
-                        }
-                        self.state = ParserState::InCodeBlock;
-                        self.current_code_block.clear();
-                    } else if self.is_path_line(line) {
-                        let extracted_path = extract_path(line);
-                        if !extracted_path.trim().is_empty() {
-                            self.current_target_path = Some(extracted_path.to_string());
-                        } else {
-                            warn!("Detected an empty file path! Skipping...");
-                            self.current_target_path = None;
-                        }
-                    } else {
-                        // Check for inline file paths
-                        let trimmed = line.trim();
-                        if !trimmed.is_empty()
-                            && !trimmed.starts_with('#')
-                            && !trimmed.contains(' ')
-                        {
-                            self.current_target_path = Some(trimmed.to_string());
-                        }
-                    }
-                }
-                ParserState::InCodeBlock => {
-                    if line.trim_start().starts_with(delimiter) {
-                        // End of code block
-                        self.finalize_current_block();
-                        self.state = ParserState::ExpectingPath;
-                    } else {
-                        self.current_code_block.push_str(line);
-                        self.current_code_block.push('\n');
-                    }
-                }
-            }
-        }
+impl CodeBlock {
+    /// Starts the report entry for this block.
+    fn report(&self) -> BlockReport {
+        let path = self.original_path.as_ref().unwrap_or(&self.target_path);
+        let target = match &self.symbol {
+            Some(symbol) => format!("{path}::{symbol}"),
+            None => path.clone(),
+        };
+        BlockReport::new(self.start_line, self.end_line, Some(target))
+    }
 
-        // Handle case where file ends without closing delimiter
-        if self.state == ParserState::InCodeBlock {
-            self.finalize_current_block();
+    /// The full file content this block proposes, given the current content of its
+    /// target. Symbol blocks replace only their symbol; `Err` carries a skip reason.
+    fn proposed_content(&self, existing: Option<&str>) -> std::result::Result<String, String> {
+        if let BlockOperation::Edit(edits) = &self.operation {
+            let existing =
+                existing.ok_or_else(|| "edit target is not an existing text file".to_string())?;
+            return apply_edits(existing, edits);
         }
-
-        self.blocks
+        if is_notebook(Path::new(&self.target_path)) && is_cell_render(&self.content) {
+            return update_notebook(existing, &self.content);
+        }
+        let Some(symbol) = &self.symbol else {
+            return Ok(self.content.clone());
+        };
+        let language = SymbolLanguage::from_path(Path::new(&self.target_path))
+            .ok_or_else(|| "symbol injection is not supported for this file type".to_string())?;
+        let existing =
+            existing.ok_or_else(|| "symbol target is not an existing text file".to_string())?;
+        replace_symbol(language, existing, symbol, &self.content)
     }
+}
+
+/// Struct for implementing the InjectOperation trait.
+#[derive(Debug, Clone, Default)]
+pub struct Injector {
+    /// Code block delimiter used in the input (defaults to "```"). Backtick and tilde
+    /// delimiters also match longer fences, as produced for files containing fences.
+    pub delimiter: Option<String>,
+    /// Commands run on injected files matching a glob, see `InjectConfig::hooks`.
+    pub hooks: Option<BTreeMap<String, String>>,
+    /// Hashes recorded when the prompt was generated. Files whose current content no
+    /// longer matches are three-way merged when a snapshot is available, and are
+    /// otherwise not overwritten, deleted or renamed unless `force` is set.
+    pub manifest: Option<Manifest>,
+    /// Overwrite files that changed since generation, with a warning instead of
+    /// merging or skipping.
+    pub force: bool,
+    /// Only consider blocks whose target matches one of these globs (all when empty).
+    pub only: Vec<String>,
+    /// Skip blocks whose target matches one of these globs.
+    pub exclude: Vec<String>,
+    /// Review every block on stdin/stderr before applying it, see [`Reviewer`].
+    pub interactive: bool,
+    /// Refusing, allowing or splicing blocks that elide code with placeholders.
+    pub placeholders: PlaceholderPolicy,
+    /// Branch, worktree and commit handling. When set, files with uncommitted
+    /// changes are skipped unless `force` is set.
+    pub git: Option<GitOptions>,
+    /// Command run in the repository after injecting (and after hooks); when it
+    /// fails, every touched file is restored to its previous content.
+    pub verify: Option<String>,
+    /// Where to write a follow-up prompt with the failure and the rejected files
+    /// when `verify` fails.
+    pub followup: Option<PathBuf>,
+    /// Only accept explicit path markers (fence-line paths, ``### `path` ``,
+    /// ``**`path`**``) that look like file paths and match the fence language, and
+    /// directives written as ``### DELETE `path` `` or ``### RENAME `from` -> `to` ``.
+    /// Lines that would otherwise be guessed to be paths or directives are reported
+    /// as warnings.
+    pub strict_paths: bool,
+    /// Which paths blocks may touch, whether they may create files or directories,
+    /// and size and block count limits. Binary files are never overwritten.
+    pub policy: WritePolicy,
+    /// Whether the input is Markdown (fences, directives, XML tags) or JSON file
+    /// operations.
+    pub format: ResponseFormat,
+    /// Write blocks that fail to parse (Python, Rust, JS/TS, JSON, YAML, TOML) instead
+    /// of skipping them; the error is noted in the report either way.
+    pub allow_syntax_errors: bool,
+}
 
-    fn is_path_line(&self, line: &str) -> bool {
-        let trimmed = line.trim_start();
-        (trimmed.starts_with("### `") && line.trim_end().ends_with('`'))
-            || (trimmed.starts_with("**`") && line.trim_end().ends_with("`**"))
-            || (trimmed.starts_with('`') && line.trim_end().ends_with('`') && line.len() > 3)
+impl InjectOperation for Injector {
+    /// Injects code from a specified input file into a target repository path.
+    /// This method encapsulates the original `inject` function's logic with security improvements.
+    /// An input path of `-` reads the response from stdin.
+    fn inject(&self, input_path: &Path, repo_path: &Path) -> Result<InjectionReport, Error> {
+        if self.interactive && is_stdin(input_path) {
+            bail!("Interactive review reads answers from stdin, so the response must come from a file");
+        }
+        info!("Starting to process the input file for injection: {input_path:?}");
+        let contents = read_input(input_path)?;
+        self.inject_response(&contents, repo_path)
     }
+}
 
-    fn finalize_current_block(&mut self) {
-        if let Some(ref target_path) = self.current_target_path {
-            if !self.current_code_block.trim().is_empty() {
-                self.blocks.push(CodeBlock {
-                    target_path: target_path.clone(),
-                    content: self.current_code_block.trim_end().to_string(),
-                });
-            } else {
-                warn!("Empty code block detected for path: {target_path:?}");
-            }
+impl Injector {
+    /// Injects a response that is already in memory, e.g. a message taken from a
+    /// chat transcript. Reviews on stdin/stderr when `interactive` is set.
+    pub fn inject_response(&self, response: &str, repo_path: &Path) -> Result<InjectionReport> {
+        if self.interactive {
+            // Prompts go to stderr so stdout stays reserved for the report
+            let mut stdin = io::stdin().lock();
+            let mut stderr = io::stderr();
+            let mut reviewer = Reviewer::new(&mut stdin, &mut stderr);
+            self.apply(response, repo_path, Some(&mut reviewer))
         } else {
-            warn!("Code block closed without a file path being set!");
+            self.apply(response, repo_path, None)
         }
-        self.current_target_path = None;
-        self.current_code_block.clear();
     }
-}
 
-impl InjectOperation for Injector {
-    /// Injects code from a specified input file into a target repository path.
-    /// This method encapsulates the original `inject` function's logic with security improvements.
-    fn inject(&self, input_path: &Path, repo_path: &Path) -> Result<(), Error> {
+    /// Like [`InjectOperation::inject`], but asks `reviewer` about every selected block
+    /// before applying it. Rejected blocks, and every block after a quit, are skipped.
+    pub fn inject_reviewed(
+        &self,
+        input_path: &Path,
+        repo_path: &Path,
+        reviewer: &mut Reviewer,
+    ) -> Result<InjectionReport> {
+        let contents = read_input(input_path)?;
+        self.apply(&contents, repo_path, Some(reviewer))
+    }
+
+    fn apply(
+        &self,
+        contents: &str,
+        repo_path: &Path,
+        mut reviewer: Option<&mut Reviewer>,
+    ) -> Result<InjectionReport> {
         // Canonicalize the base repo path
         let base_path_canon = fs::canonicalize(repo_path).with_context(|| {
             format!(
@@ -139,71 +200,366 @@ impl InjectOperation for Injector {
         })?;
         info!("Canonicalized base repository path: {base_path_canon:?}");
 
-        let contents = fs::read_to_string(input_path)
-            .with_context(|| format!("Failed to read input file: '{}'", input_path.display()))?;
-
-        info!("Starting to process the input file for injection: {input_path:?}");
+        let only = compile_globs(&self.only)?;
+        let exclude = compile_globs(&self.exclude)?;
+        let detector = PlaceholderDetector::new(&self.placeholders)?;
+        let policy = PolicyChecker::new(&self.policy)?;
 
         // Parse the input file using the new parser
-        let parser = InjectionParser::new();
-        let code_blocks = parser.parse(&contents);
+        let parsed = match self.format {
+            ResponseFormat::Markdown => {
+                let options = ParseOptions {
+                    delimiter: self.delimiter.clone(),
+                    strict_paths: self.strict_paths,
+                    format: self.format,
+                };
+                parse_response_with_diagnostics(contents, &options)
+            }
+            ResponseFormat::Json => parse_json_response(contents)?,
+        };
+        // Refused before any branch, worktree or file is touched
+        if let Err(reason) = policy.check_block_count(parsed.blocks.len()) {
+            bail!("Refusing to inject: {reason}");
+        }
+
+        let (base_path_canon, git_root) = match &self.git {
+            Some(git_options) => {
+                let (base, root) = prepare_git(git_options, base_path_canon)?;
+                (base, Some(root))
+            }
+            None => (base_path_canon, None),
+        };
+        let dirty = match &git_root {
+            Some(root) if !self.force => git::dirty_paths(root)?,
+            _ => HashSet::new(),
+        };
+
+        let mut block_reports = parsed.skipped;
+        let warnings = parsed.warnings;
+
+        let resolver = PathResolver::new(&base_path_canon);
+        let mut backup = Backup::default();
 
         // Process each code block
-        for block in code_blocks {
-            self.inject_code_block(&block, &base_path_canon)?;
+        for block in parsed.blocks {
+            let mut block = CodeBlock::from(block);
+            if let Some(reason) = resolve_block_paths(&resolver, &mut block, &base_path_canon) {
+                warn!("Skipping {:?}: {reason}", block.target_path);
+                block_reports.push(block.report().skip(reason));
+                continue;
+            }
+            // Filters see the resolved path, e.g. `src/lib.rs` for `myrepo/src/lib.rs`
+            let target = block.target_path.trim_start_matches("./");
+            if (!only.is_empty() && !only.iter().any(|p| p.matches(target)))
+                || exclude.iter().any(|p| p.matches(target))
+            {
+                info!(
+                    "Skipping {:?}: filtered out by --only/--exclude",
+                    block.target_path
+                );
+                block_reports.push(block.report().skip("filtered out by --only/--exclude"));
+                continue;
+            }
+            if let Err(reason) = check_policy(&policy, &block, &base_path_canon) {
+                warn!("Skipping {:?}: {reason}", block.target_path);
+                block_reports.push(block.report().skip(reason));
+                continue;
+            }
+            if touches_dirty(&block, &base_path_canon, &dirty)? {
+                warn!(
+                    "{:?} has uncommitted changes. Skipping... (use --force to overwrite)",
+                    block.target_path
+                );
+                block_reports.push(block.report().skip("uncommitted changes"));
+                continue;
+            }
+            if let Some(reviewer) = reviewer.as_deref_mut() {
+                if reviewer.has_quit() {
+                    block_reports.push(block.report().skip("review stopped"));
+                    continue;
+                }
+                match review_block(reviewer, &block, &base_path_canon)? {
+                    ReviewDecision::Accept => {}
+                    ReviewDecision::Edited(content) => {
+                        // The editor was given the whole file, not just the symbol or edits
+                        block.content = content;
+                        block.symbol = None;
+                        block.operation = BlockOperation::Write;
+                    }
+                    ReviewDecision::Reject => {
+                        block_reports.push(block.report().skip("rejected during review"));
+                        continue;
+                    }
+                    ReviewDecision::Quit => {
+                        block_reports.push(block.report().skip("review stopped"));
+                        continue;
+                    }
+                }
+            }
+
+            if self.verify.is_some() {
+                backup.save(&backup_path(&block.target_path, &base_path_canon));
+                if let BlockOperation::Rename(destination) = &block.operation {
+                    backup.save(&backup_path(destination, &base_path_canon));
+                }
+            }
+            let applied = match &block.operation {
+                BlockOperation::Write | BlockOperation::Edit(_) => {
+                    self.inject_code_block(&block, &base_path_canon, &detector, &policy)
+                }
+                BlockOperation::Delete => self.delete_file(&block, &base_path_canon),
+                BlockOperation::Rename(destination) => {
+                    self.rename_file(&block, destination, &base_path_canon)
+                }
+            };
+            // An I/O failure skips this block; earlier blocks stay reported and verifiable
+            let block_report = applied.unwrap_or_else(|e| {
+                error!("Failed to apply {:?}: {e:#}", block.target_path);
+                block.report().skip(format!("{e:#}"))
+            });
+            block_reports.push(block_report);
+        }
+        block_reports.sort_by_key(|b| b.start_line);
+
+        let mut report = InjectionReport {
+            blocks: block_reports,
+            warnings,
+            ..Default::default()
+        };
+
+        if let Some(hooks) = &self.hooks {
+            let written_files: Vec<PathBuf> = report
+                .written_paths()
+                .into_iter()
+                .map(Path::to_path_buf)
+                .collect();
+            report.hooks = run_hooks(hooks, &written_files, &base_path_canon);
+            let failed = report.hooks.iter().filter(|o| !o.success()).count();
+            info!(
+                "Ran {} hook command(s) on {} injected file(s), {failed} failed.",
+                report.hooks.len(),
+                written_files.len()
+            );
+        }
+
+        if let Some(command) = &self.verify {
+            let mut outcome = run_verify(command, &base_path_canon);
+            if !outcome.success() {
+                let touched: Vec<&Path> = report.touched_paths();
+                if let Some(followup) = &self.followup {
+                    let files = touched
+                        .iter()
+                        .map(|path| {
+                            let key = path.to_string_lossy().replace('\\', "/");
+                            (key, fs::read_to_string(base_path_canon.join(path)).ok())
+                        })
+                        .collect();
+                    let delimiter = self.delimiter.as_deref().unwrap_or("```");
+                    write_followup_prompt(followup, &outcome, &files, delimiter)?;
+                    info!("Wrote follow-up prompt to {followup:?}");
+                }
+                let touched: Vec<PathBuf> = touched
+                    .into_iter()
+                    .map(|path| base_path_canon.join(path))
+                    .collect();
+                backup.restore(&touched)?;
+                outcome.rolled_back = true;
+                warn!(
+                    "Verify command failed. Rolled back {} touched file(s).",
+                    touched.len()
+                );
+            }
+            report.verification = Some(outcome);
+        }
+
+        let verified = report
+            .verification
+            .as_ref()
+            .is_none_or(CommandOutcome::success);
+        if let (true, Some(root), Some(message)) = (
+            verified,
+            &git_root,
+            self.git.as_ref().and_then(|g| g.commit_message.as_deref()),
+        ) {
+            let touched: Vec<PathBuf> = report
+                .touched_paths()
+                .into_iter()
+                .map(|path| base_path_canon.join(path))
+                .collect();
+            report.commit = git::commit_paths(root, &touched, message)?;
         }
 
         info!("Finished processing the input file for injection.");
-        Ok(())
+        Ok(report)
     }
-}
 
-impl Injector {
-    /// Injects a single code block into the target file system
+    /// Injects a single code block into the target file system and reports what
+    /// happened to it.
     /// Time complexity: O(1) for path validation, O(n) for file I/O where n is content size
     /// Space complexity: O(m) where m is the size of the code block content
-    fn inject_code_block(&self, block: &CodeBlock, base_path_canon: &PathBuf) -> Result<()> {
-        // Construct the full target path
-        let full_target_path = base_path_canon.join(&block.target_path);
-
-        // Extract filename
-        let target_filename = match full_target_path.file_name() {
-            Some(name) => name.to_os_string(),
-            None => {
-                error!("Could not extract filename from path: {full_target_path:?}");
-                return Ok(()); // Skip this file and continue
-            }
-        };
+    fn inject_code_block(
+        &self,
+        block: &CodeBlock,
+        base_path_canon: &Path,
+        detector: &PlaceholderDetector,
+        policy: &PolicyChecker,
+    ) -> Result<BlockReport> {
+        let mut report = block.report();
+        let final_file_path_canon =
+            match resolve_in_repo(&block.target_path, base_path_canon, true)? {
+                Ok(path) => path,
+                Err(reason) => return Ok(report.skip(reason)), // Skip this file and continue to the next
+            };
+        report.resolved_path = Some(relative_to(&final_file_path_canon, base_path_canon));
+        let target_filename = final_file_path_canon
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        let canonical_parent_dir = final_file_path_canon
+            .parent()
+            .unwrap_or(base_path_canon)
+            .to_path_buf();
 
-        let parent_dir_for_file = full_target_path.parent().unwrap_or_else(|| Path::new(""));
+        info!("Final canonical file path for injection: {final_file_path_canon:?}");
 
-        // Ensure parent directory exists and canonicalize it
-        let canonical_parent_dir = if parent_dir_for_file.as_os_str().is_empty()
-            || parent_dir_for_file == base_path_canon.as_path()
-        {
-            base_path_canon.clone()
-        } else {
-            fs::create_dir_all(parent_dir_for_file).with_context(|| {
-                format!("Failed to create parent directory: {parent_dir_for_file:?}")
-            })?;
-            fs::canonicalize(parent_dir_for_file).with_context(|| {
-                format!("Failed to canonicalize parent directory: {parent_dir_for_file:?}")
-            })?
+        // Keep the line endings, BOM, trailing newline and permissions of an existing target
+        let existing = fs::read(&final_file_path_canon).ok();
+        if existing.as_deref().is_some_and(is_binary) {
+            warn!("{final_file_path_canon:?} is a binary file. Skipping...");
+            return Ok(report.skip("refusing to overwrite a binary file"));
+        }
+        let style = existing
+            .as_deref()
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+            .map(FileStyle::detect)
+            .unwrap_or_default();
+        let proposed = block.proposed_content(
+            existing
+                .as_deref()
+                .and_then(|bytes| std::str::from_utf8(bytes).ok()),
+        );
+        let mut new_content = match proposed {
+            Ok(content) => style.apply(&content),
+            Err(reason) => {
+                warn!(
+                    "Cannot inject {:?}: {reason}. Skipping...",
+                    block.report().target_path
+                );
+                return Ok(report.skip(reason));
+            }
         };
 
-        let final_file_path_canon = canonical_parent_dir.join(&target_filename);
-
-        // SECURITY CHECK: Verify the final path is still within the base repository
-        if !final_file_path_canon.starts_with(base_path_canon) {
-            error!(
-                "Security risk: Attempted to write to a path outside the repository: {:?}. \
-                Target path: {:?}, Base path: {:?}",
-                final_file_path_canon, block.target_path, base_path_canon
-            );
-            return Ok(()); // Skip this file and continue to the next
+        if existing.as_deref() == Some(new_content.as_bytes()) {
+            info!("Content of {final_file_path_canon:?} is unchanged. Skipping write.");
+            report.action = BlockAction::Unchanged;
+            return Ok(report);
         }
-
-        info!("Final canonical file path for injection: {final_file_path_canon:?}");
+        let original = existing
+            .as_deref()
+            .and_then(|bytes| std::str::from_utf8(bytes).ok());
+        match detector.check(&final_file_path_canon, original, &new_content) {
+            Some(_) if self.placeholders.allow => {
+                warn!("{final_file_path_canon:?}: block looks incomplete. Writing anyway.");
+            }
+            Some(LazyOutput::Placeholders(lines)) => {
+                let spliced = match original {
+                    Some(original) if self.placeholders.splice => {
+                        splice(original, &new_content, &lines)
+                    }
+                    _ => None,
+                };
+                if let Some(spliced) = spliced {
+                    info!("Spliced elided regions of {final_file_path_canon:?} back in.");
+                    report.reason = Some("spliced elided regions from the original".to_string());
+                    new_content = spliced;
+                } else {
+                    let placeholder = new_content.lines().nth(lines[0]).unwrap_or("").trim();
+                    error!(
+                        "{final_file_path_canon:?}: block contains the placeholder {placeholder:?}. \
+                        Skipping... (use --splice-placeholders or --allow-placeholders)"
+                    );
+                    return Ok(report.skip(format!("contains placeholder {placeholder:?}")));
+                }
+            }
+            Some(LazyOutput::Shrunk(percent)) => {
+                error!(
+                    "{final_file_path_canon:?}: block is {percent}% shorter than the file. \
+                    Skipping... (use --allow-placeholders to write it anyway)"
+                );
+                return Ok(report.skip(format!("{percent}% shorter than the original")));
+            }
+            None => {}
+        }
+        if let Some(syntax_error) = check_syntax(&final_file_path_canon, &new_content) {
+            // Files that already fail to parse are left to the user
+            let original_parses =
+                original.is_none_or(|o| check_syntax(&final_file_path_canon, o).is_none());
+            if !original_parses {
+                info!("{final_file_path_canon:?} did not parse before either: {syntax_error}");
+            } else if self.allow_syntax_errors {
+                warn!("{final_file_path_canon:?}: {syntax_error}. Writing anyway.");
+                report.reason = Some(format!("{syntax_error} (written anyway)"));
+            } else {
+                error!(
+                    "{final_file_path_canon:?}: {syntax_error}. \
+                    Skipping... (use --allow-syntax-errors to write it anyway)"
+                );
+                return Ok(report.skip(syntax_error.to_string()));
+            }
+        }
+        if let Some(entry) =
+            self.stale_entry(&final_file_path_canon, base_path_canon, existing.as_deref())
+        {
+            let ours = existing
+                .as_deref()
+                .and_then(|bytes| std::str::from_utf8(bytes).ok());
+            match (entry.snapshot.as_deref(), ours) {
+                _ if self.force => {
+                    warn!(
+                        "{final_file_path_canon:?} changed since the prompt was generated. \
+                        Overwriting because of --force."
+                    );
+                    report.reason = Some("overwrote local changes (--force)".to_string());
+                }
+                (Some(base), Some(ours)) => {
+                    // Keep the local edits and apply the model's changes on top of them
+                    let merged = merge_texts(base, ours, &new_content);
+                    if merged.has_conflicts {
+                        warn!(
+                            "{final_file_path_canon:?} changed since the prompt was generated \
+                            and the merge has conflicts. Writing conflict markers."
+                        );
+                        report.reason = Some("merged with local changes, conflicts".to_string());
+                    } else {
+                        info!(
+                            "{final_file_path_canon:?} changed since the prompt was generated. \
+                            Merged cleanly."
+                        );
+                        report.reason = Some("merged with local changes".to_string());
+                    }
+                    new_content = merged.content;
+                    if ours == new_content {
+                        info!("Merged content of {final_file_path_canon:?} is unchanged. Skipping write.");
+                        report.action = BlockAction::Unchanged;
+                        return Ok(report);
+                    }
+                }
+                _ => {
+                    error!(
+                        "{final_file_path_canon:?} changed since the prompt was generated and \
+                        no snapshot is available to merge. Skipping... (use --force to overwrite)"
+                    );
+                    return Ok(report.skip("file changed since the prompt was generated"));
+                }
+            }
+        }
+        if let Err(reason) = policy.check_size(&new_content) {
+            warn!("{final_file_path_canon:?}: {reason}. Skipping...");
+            return Ok(report.skip(reason));
+        }
+        let existing_permissions = fs::metadata(&final_file_path_canon)
+            .ok()
+            .map(|metadata| metadata.permissions());
 
         // Generate a secure temporary filename
         let mut rng = ThreadRng::default();
@@ -219,11 +575,19 @@ impl Injector {
         );
         let temp_file_path = canonical_parent_dir.join(temp_filename);
 
+        // Directories are only created once every check has passed
+        create_parent_dir(&canonical_parent_dir)?;
         info!("Writing to temporary file: {temp_file_path:?}");
 
         // Write to temporary file and atomically rename
-        fs::write(&temp_file_path, &block.content)
+        fs::write(&temp_file_path, &new_content)
             .with_context(|| format!("Failed to write to temporary file: {temp_file_path:?}"))?;
+        if let Some(permissions) = existing_permissions {
+            fs::set_permissions(&temp_file_path, permissions).with_context(|| {
+                let _ = fs::remove_file(&temp_file_path);
+                format!("Failed to copy permissions to temporary file: {temp_file_path:?}")
+            })?;
+        }
 
         info!("Successfully wrote to temporary file. Renaming to: {final_file_path_canon:?}");
 
@@ -236,44 +600,375 @@ impl Injector {
         })?;
 
         info!("Successfully injected code into {final_file_path_canon:?}");
-        Ok(())
+        let old_len = existing.as_ref().map_or(0, Vec::len);
+        report.action = if existing.is_some() {
+            BlockAction::Modified
+        } else {
+            BlockAction::Created
+        };
+        report.byte_delta = new_content.len() as i64 - old_len as i64;
+        Ok(report)
+    }
+
+    /// Removes a file from the repository as requested by a `DELETE` directive.
+    /// Missing files and directories are skipped with a warning.
+    fn delete_file(&self, block: &CodeBlock, base_path_canon: &Path) -> Result<BlockReport> {
+        let mut report = block.report();
+        let final_file_path_canon =
+            match resolve_in_repo(&block.target_path, base_path_canon, false)? {
+                Ok(path) => path,
+                Err(reason) => return Ok(report.skip(reason)),
+            };
+        report.resolved_path = Some(relative_to(&final_file_path_canon, base_path_canon));
+
+        if !final_file_path_canon.is_file() {
+            warn!("Cannot delete {final_file_path_canon:?}: not an existing file. Skipping...");
+            return Ok(report.skip("not an existing file"));
+        }
+        let current = fs::read(&final_file_path_canon).ok();
+        if self.is_stale(&final_file_path_canon, base_path_canon, current.as_deref()) {
+            return Ok(report.skip("file changed since the prompt was generated"));
+        }
+
+        fs::remove_file(&final_file_path_canon)
+            .with_context(|| format!("Failed to delete file: {final_file_path_canon:?}"))?;
+
+        info!("Successfully deleted {final_file_path_canon:?}");
+        report.action = BlockAction::Deleted;
+        report.byte_delta = -(current.map_or(0, |bytes| bytes.len()) as i64);
+        Ok(report)
+    }
+
+    /// Moves a file inside the repository as requested by a `RENAME` directive.
+    /// Both ends must resolve inside the repository and an existing destination
+    /// is never overwritten.
+    fn rename_file(
+        &self,
+        block: &CodeBlock,
+        to: &str,
+        base_path_canon: &Path,
+    ) -> Result<BlockReport> {
+        let mut report = block.report();
+        let source_canon = match resolve_in_repo(&block.target_path, base_path_canon, false)? {
+            Ok(path) => path,
+            Err(reason) => return Ok(report.skip(reason)),
+        };
+        report.resolved_path = Some(relative_to(&source_canon, base_path_canon));
+        if !source_canon.is_file() {
+            warn!("Cannot rename {source_canon:?}: not an existing file. Skipping...");
+            return Ok(report.skip("not an existing file"));
+        }
+        let current = fs::read(&source_canon).ok();
+        if self.is_stale(&source_canon, base_path_canon, current.as_deref()) {
+            return Ok(report.skip("file changed since the prompt was generated"));
+        }
+
+        let destination_canon = match resolve_in_repo(to, base_path_canon, true)? {
+            Ok(path) => path,
+            Err(reason) => return Ok(report.skip(format!("destination: {reason}"))),
+        };
+        report.destination = Some(relative_to(&destination_canon, base_path_canon));
+        if destination_canon.exists() {
+            warn!(
+                "Cannot rename {source_canon:?} to {destination_canon:?}: destination already exists. Skipping..."
+            );
+            return Ok(report.skip("destination already exists"));
+        }
+
+        create_parent_dir(destination_canon.parent().unwrap_or(base_path_canon))?;
+        fs::rename(&source_canon, &destination_canon).with_context(|| {
+            format!("Failed to rename {source_canon:?} to {destination_canon:?}")
+        })?;
+
+        info!("Successfully renamed {source_canon:?} to {destination_canon:?}");
+        report.action = BlockAction::Renamed;
+        Ok(report)
+    }
+
+    /// Compares a target's current bytes with the hash recorded in the manifest and
+    /// returns the manifest entry when the file changed since generation. Files absent
+    /// from the manifest (or injections without a manifest) are never stale.
+    fn stale_entry(
+        &self,
+        target_canon: &Path,
+        base_path_canon: &Path,
+        current: Option<&[u8]>,
+    ) -> Option<&ManifestEntry> {
+        let relative = target_canon
+            .strip_prefix(base_path_canon)
+            .unwrap_or(target_canon);
+        let entry = self.manifest.as_ref()?.get(relative)?;
+        (current.map(sha256_hex).as_deref() != Some(entry.sha256.as_str())).then_some(entry)
+    }
+
+    /// Returns true when a file changed since generation and must be skipped by a
+    /// delete or rename; with `force` the change is only reported.
+    fn is_stale(
+        &self,
+        target_canon: &Path,
+        base_path_canon: &Path,
+        current: Option<&[u8]>,
+    ) -> bool {
+        if self
+            .stale_entry(target_canon, base_path_canon, current)
+            .is_none()
+        {
+            return false;
+        }
+        let relative = target_canon
+            .strip_prefix(base_path_canon)
+            .unwrap_or(target_canon);
+
+        if self.force {
+            warn!("{relative:?} changed since the prompt was generated. Overwriting because of --force.");
+            false
+        } else {
+            error!("{relative:?} changed since the prompt was generated. Skipping... (use --force to overwrite)");
+            true
+        }
+    }
+}
+
+/// Absolute path used to back up a target, matching the canonical paths the
+/// report is built from.
+fn backup_path(relative_path: &str, base_path_canon: &Path) -> PathBuf {
+    let joined = base_path_canon.join(relative_path);
+    match (
+        joined
+            .parent()
+            .and_then(|parent| fs::canonicalize(parent).ok()),
+        joined.file_name(),
+    ) {
+        (Some(parent), Some(file_name)) => parent.join(file_name),
+        _ => joined,
+    }
+}
+
+/// Maps the block's paths to repository files, see [`PathResolver`], and validates
+/// them with [`validate_target`]. Returns a skip reason when the target matches
+/// several files or a path is rejected.
+fn resolve_block_paths(
+    resolver: &PathResolver,
+    block: &mut CodeBlock,
+    base_path_canon: &Path,
+) -> Option<String> {
+    let resolved = match resolver.resolve(&block.target_path) {
+        ResolvedPath::Existing(path) | ResolvedPath::New(path) => path,
+        ResolvedPath::Ambiguous(candidates) => {
+            return Some(format!("ambiguous path, matches {}", candidates.join(", ")));
+        }
+    };
+    if resolved != block.target_path {
+        info!("Resolved {:?} to {resolved:?}", block.target_path);
+        block.original_path = Some(std::mem::replace(&mut block.target_path, resolved));
+    }
+    if let Err(reason) = validate_target(base_path_canon, &block.target_path) {
+        return Some(reason);
+    }
+    if let BlockOperation::Rename(destination) = &mut block.operation {
+        *destination = resolver.normalize(destination);
+        if let Err(reason) = validate_target(base_path_canon, destination) {
+            return Some(format!("destination rejected: {reason}"));
+        }
+    }
+    None
+}
+
+/// Applies `<search>`/`<replace>` pairs in order. Each search text must occur
+/// exactly once; in CRLF files it also matches with CRLF line breaks.
+fn apply_edits(existing: &str, edits: &[SearchReplace]) -> std::result::Result<String, String> {
+    let mut content = existing.to_string();
+    for (index, edit) in edits.iter().enumerate() {
+        let mut search = edit.search.clone();
+        let mut replace = edit.replace.clone();
+        if !content.contains(&search) && content.contains("\r\n") {
+            search = search.replace('\n', "\r\n");
+            replace = replace.replace('\n', "\r\n");
+        }
+        match content.matches(&search).count() {
+            1 => content = content.replacen(&search, &replace, 1),
+            0 => return Err(format!("search text {} not found", index + 1)),
+            n => return Err(format!("search text {} matches {n} times", index + 1)),
+        }
+    }
+    Ok(content)
+}
+
+/// Applies the path and creation rules of the write policy to a resolved block.
+fn check_policy(
+    policy: &PolicyChecker,
+    block: &CodeBlock,
+    base_path_canon: &Path,
+) -> std::result::Result<(), String> {
+    policy.check_path(&block.target_path)?;
+    match &block.operation {
+        BlockOperation::Write | BlockOperation::Edit(_) => {
+            policy.check_creation(base_path_canon, &block.target_path)
+        }
+        BlockOperation::Delete => Ok(()),
+        BlockOperation::Rename(destination) => {
+            policy.check_path(destination)?;
+            policy.check_creation(base_path_canon, destination)
+        }
+    }
+}
+
+/// Creates the requested branch or worktree and returns the base path to inject
+/// into (moved into the worktree when one was added) and the work tree root.
+fn prepare_git(git_options: &GitOptions, base_path_canon: PathBuf) -> Result<(PathBuf, PathBuf)> {
+    let root = git::toplevel(&base_path_canon)?;
+    match (&git_options.worktree, &git_options.branch) {
+        (Some(worktree), Some(branch)) => {
+            // git resolves a relative path against the repository, not the working directory
+            let worktree = std::env::current_dir()?.join(worktree);
+            git::add_worktree(&root, &worktree, branch)?;
+            let worktree_root = fs::canonicalize(&worktree)
+                .with_context(|| format!("Failed to canonicalize worktree: {worktree:?}"))?;
+            // Keep injecting into the same subdirectory, now inside the worktree
+            let subdir = base_path_canon.strip_prefix(&root).unwrap_or(Path::new(""));
+            Ok((worktree_root.join(subdir), worktree_root))
+        }
+        (Some(_), None) => bail!("A git worktree needs a branch name"),
+        (None, Some(branch)) => {
+            git::create_branch(&root, branch)?;
+            Ok((base_path_canon, root))
+        }
+        (None, None) => Ok((base_path_canon, root)),
+    }
+}
+
+/// True when the file a block writes, deletes or renames has uncommitted changes.
+fn touches_dirty(
+    block: &CodeBlock,
+    base_path_canon: &Path,
+    dirty: &HashSet<PathBuf>,
+) -> Result<bool> {
+    if dirty.is_empty() || !base_path_canon.join(&block.target_path).exists() {
+        return Ok(false);
     }
+    Ok(resolve_in_repo(&block.target_path, base_path_canon, false)?
+        .is_ok_and(|path| dirty.contains(&path)))
 }
 
-/// Helper function for extracting the path from a line
-/// that looks like `### `path/to/file` or **`path/to/file`**, etc.
-fn extract_path(input: &str) -> &str {
-    // Trim leading/trailing whitespace which might affect path extraction
-    let trimmed_input = input.trim();
-    if trimmed_input.starts_with("### `") && trimmed_input.ends_with('`') {
-        &trimmed_input[5..trimmed_input.len() - 1]
-    } else if trimmed_input.starts_with("**`") && trimmed_input.ends_with("`**") {
-        &trimmed_input[3..trimmed_input.len() - 3]
-    } else if trimmed_input.starts_with('`') && trimmed_input.ends_with('`') {
-        // Generic backtick case
-        &trimmed_input[1..trimmed_input.len() - 1]
-    } else {
-        trimmed_input // Fallback if no known pattern matches, assume the line itself is the path
+/// Shows the change a block would make and asks the reviewer about it.
+fn review_block(
+    reviewer: &mut Reviewer,
+    block: &CodeBlock,
+    base_path_canon: &Path,
+) -> Result<ReviewDecision> {
+    match &block.operation {
+        BlockOperation::Write | BlockOperation::Edit(_) => {
+            let existing = resolve_in_repo(&block.target_path, base_path_canon, false)?
+                .ok()
+                .and_then(|path| fs::read_to_string(path).ok());
+            let style = existing
+                .as_deref()
+                .map(FileStyle::detect)
+                .unwrap_or_default();
+            match block.proposed_content(existing.as_deref()) {
+                Ok(proposed) => reviewer.review_write(
+                    &block.target_path,
+                    existing.as_deref(),
+                    &style.apply(&proposed),
+                ),
+                // Nothing to show; the injection reports why it is skipped
+                Err(_) => Ok(ReviewDecision::Accept),
+            }
+        }
+        BlockOperation::Delete => {
+            reviewer.review_operation(&format!("Delete {}", block.target_path))
+        }
+        BlockOperation::Rename(destination) => {
+            reviewer.review_operation(&format!("Rename {} -> {destination}", block.target_path))
+        }
     }
 }
 
-/// Attempt to extract a file path from a line that begins with the code block
-/// delimiter. This supports prmpt's own output format where the file path
-/// directly follows the opening fence, e.g. "```src/lib.rs" or
-/// "```rust src/lib.rs".
-fn extract_path_from_fence<'a>(line: &'a str, delimiter: &str) -> Option<&'a str> {
-    let remainder = line.trim_start().strip_prefix(delimiter)?.trim();
-    if remainder.is_empty() {
-        return None;
+/// Resolves a repository-relative path to its canonical location, applying the
+/// repository-root security check. The inner `Err` carries the reason the path has
+/// to be skipped (rejected by [`validate_target`], no file name, escapes the
+/// repository, or a missing parent directory unless `allow_missing_parent` is
+/// set); the outer one is an I/O failure. Nothing is created: with
+/// `allow_missing_parent`, the path is where the file will be once the caller
+/// creates its directories.
+fn resolve_in_repo(
+    relative_path: &str,
+    base_path_canon: &Path,
+    allow_missing_parent: bool,
+) -> Result<std::result::Result<PathBuf, String>> {
+    // Symlinked ancestors are refused before any directory is created through them
+    if let Err(reason) = validate_target(base_path_canon, relative_path) {
+        return Ok(Err(reason));
+    }
+
+    // Construct the full target path
+    let full_target_path = base_path_canon.join(relative_path);
+
+    // Extract filename
+    let target_filename = match full_target_path.file_name() {
+        Some(name) => name.to_os_string(),
+        None => {
+            error!("Could not extract filename from path: {full_target_path:?}");
+            return Ok(Err(
+                "could not extract a file name from the path".to_string()
+            ));
+        }
+    };
+
+    let parent_dir_for_file = full_target_path.parent().unwrap_or_else(|| Path::new(""));
+
+    // Canonicalize the parent directory, or the part of it that exists so far
+    let canonical_parent_dir =
+        if parent_dir_for_file.as_os_str().is_empty() || parent_dir_for_file == base_path_canon {
+            base_path_canon.to_path_buf()
+        } else if parent_dir_for_file.is_dir() {
+            fs::canonicalize(parent_dir_for_file).with_context(|| {
+                format!("Failed to canonicalize parent directory: {parent_dir_for_file:?}")
+            })?
+        } else if allow_missing_parent {
+            // Created by the caller right before writing; the existing ancestors were
+            // checked for symlinks above
+            let existing = parent_dir_for_file
+                .ancestors()
+                .find(|ancestor| ancestor.exists())
+                .unwrap_or(base_path_canon);
+            let missing = parent_dir_for_file
+                .strip_prefix(existing)
+                .unwrap_or(Path::new(""));
+            fs::canonicalize(existing)
+                .with_context(|| format!("Failed to canonicalize directory: {existing:?}"))?
+                .join(missing)
+        } else {
+            warn!("Parent directory does not exist: {parent_dir_for_file:?}. Skipping...");
+            return Ok(Err("parent directory does not exist".to_string()));
+        };
+
+    let final_file_path_canon = canonical_parent_dir.join(&target_filename);
+
+    // SECURITY CHECK: Verify the final path is still within the base repository
+    if !final_file_path_canon.starts_with(base_path_canon) {
+        error!(
+            "Security risk: Attempted to write to a path outside the repository: {final_file_path_canon:?}. \
+            Target path: {relative_path:?}, Base path: {base_path_canon:?}"
+        );
+        return Ok(Err("path escapes the repository".to_string()));
     }
-    // If multiple tokens exist after the delimiter, assume the last one is the path
-    let tokens: Vec<&str> = remainder.split_whitespace().collect();
-    if tokens.len() == 1 {
-        let t = tokens[0];
-        if t.contains('/') || t.contains('.') {
-            return Some(t);
-        }
-        return None;
+
+    Ok(Ok(final_file_path_canon))
+}
+
+/// Creates `dir` and its missing ancestors for a file about to be written.
+fn create_parent_dir(dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create parent directory: {dir:?}"))?;
     }
-    tokens.last().copied()
+    Ok(())
+}
+
+/// Returns `path` relative to the repository root, for reporting.
+fn relative_to(path: &Path, base_path_canon: &Path) -> PathBuf {
+    path.strip_prefix(base_path_canon)
+        .unwrap_or(path)
+        .to_path_buf()
 }
@@ -0,0 +1,199 @@
+//! Reads file operations from JSON responses, as returned by models called with
+//! structured output or tool calling. The accepted shapes are:
+//!
+//! - `{"files": [op, ...]}` (or `"operations"`), a bare `[op, ...]`, or a single op;
+//! - tool calls wrapping an op in `arguments` (an object or a JSON-encoded string),
+//!   optionally inside `function`, as in `{"name": "delete_file", "arguments": ...}`.
+//!
+//! An op is `{"op": "write", "path", "content"}` (the default when `content` is
+//! present, with an optional `symbol`), `{"op": "edit", "path", "edits": [{"search",
+//! "replace"}]}`, `{"op": "delete", "path"}` or `{"op": "rename", "path", "to"}`.
+
+use crate::prmpt::report::BlockReport;
+use crate::prmpt::response::{
+    BlockOperation, ParsedBlock, ParsedResponse, PathMarker, SearchReplace,
+};
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// One file operation as written in the JSON.
+#[derive(Debug, Deserialize)]
+struct JsonOperation {
+    #[serde(alias = "action", alias = "type")]
+    op: Option<String>,
+    #[serde(alias = "file_path", alias = "file")]
+    path: Option<String>,
+    content: Option<String>,
+    symbol: Option<String>,
+    language: Option<String>,
+    edits: Option<Vec<SearchReplace>>,
+    /// Shorthand for a single edit
+    search: Option<String>,
+    replace: Option<String>,
+    #[serde(alias = "new_path", alias = "destination")]
+    to: Option<String>,
+}
+
+/// Parses a JSON response into blocks. Operations that cannot be used are reported
+/// as skipped; their line span is their 1-based position in the list of operations.
+pub fn parse_json_response(response: &str) -> Result<ParsedResponse> {
+    let root: Value = serde_json::from_str(unfence(response)).context("Invalid JSON response")?;
+    let mut operations = Vec::new();
+    collect_operations(root, None, &mut operations)?;
+
+    let mut parsed = ParsedResponse::default();
+    for (index, (tool, value)) in operations.into_iter().enumerate() {
+        let position = index + 1;
+        let path = value
+            .get("path")
+            .or_else(|| value.get("file_path"))
+            .or_else(|| value.get("file"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        match to_block(value, tool.as_deref(), position) {
+            Ok(block) => parsed.blocks.push(block),
+            Err(reason) => {
+                warn!("Skipping JSON operation {position}: {reason}");
+                parsed
+                    .skipped
+                    .push(BlockReport::new(position, position, path).skip(reason));
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// Strips a Markdown fence (```` ```json ````) wrapped around the whole response.
+fn unfence(response: &str) -> &str {
+    let trimmed = response.trim();
+    if !trimmed.starts_with("```") {
+        return response;
+    }
+    let body = trimmed.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().strip_suffix("```").unwrap_or(body)
+}
+
+/// Flattens the accepted shapes into operation objects, each with the name of the
+/// tool call it came from.
+fn collect_operations(
+    value: Value,
+    tool: Option<String>,
+    operations: &mut Vec<(Option<String>, Value)>,
+) -> Result<()> {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                collect_operations(item, tool.clone(), operations)?;
+            }
+        }
+        Value::Object(mut object) => {
+            if let Some(list) = object
+                .remove("files")
+                .or_else(|| object.remove("operations"))
+            {
+                return collect_operations(list, tool, operations);
+            }
+            if let Some(Value::Object(function)) = object.remove("function") {
+                return collect_operations(Value::Object(function), tool, operations);
+            }
+            if let Some(arguments) = object.remove("arguments") {
+                let name = object
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .or(tool);
+                let arguments = match arguments {
+                    Value::String(encoded) => serde_json::from_str(&encoded)
+                        .context("Invalid JSON in tool call arguments")?,
+                    arguments => arguments,
+                };
+                return collect_operations(arguments, name, operations);
+            }
+            operations.push((tool, Value::Object(object)));
+        }
+        other => operations.push((tool, other)),
+    }
+    Ok(())
+}
+
+/// Converts one operation, inferring its kind from `op`, the tool name, or the
+/// fields present.
+fn to_block(value: Value, tool: Option<&str>, position: usize) -> Result<ParsedBlock, String> {
+    let operation: JsonOperation =
+        serde_json::from_value(value).map_err(|e| format!("invalid operation: {e}"))?;
+    let path = operation
+        .path
+        .filter(|p| !p.trim().is_empty())
+        .ok_or_else(|| "operation without a path".to_string())?;
+    let kind = match (&operation.op, tool) {
+        (Some(op), _) => op.to_ascii_lowercase(),
+        (None, Some(tool)) => {
+            let tool = tool.to_ascii_lowercase();
+            ["delete", "rename", "edit"]
+                .into_iter()
+                .find(|kind| tool.contains(kind))
+                .or_else(|| tool.contains("move").then_some("rename"))
+                .or_else(|| tool.contains("replace").then_some("edit"))
+                .unwrap_or("write")
+                .to_string()
+        }
+        (None, None) if operation.edits.is_some() || operation.search.is_some() => {
+            "edit".to_string()
+        }
+        (None, None) if operation.to.is_some() => "rename".to_string(),
+        (None, None) => "write".to_string(),
+    };
+
+    let mut content = String::new();
+    let block_operation = match kind.as_str() {
+        "write" | "create" | "update" => {
+            content = operation
+                .content
+                .ok_or_else(|| "write without content".to_string())?;
+            if content.trim().is_empty() {
+                return Err("empty code block".to_string());
+            }
+            BlockOperation::Write
+        }
+        "edit" => {
+            let mut edits = operation.edits.unwrap_or_default();
+            if let Some(search) = operation.search {
+                edits.push(SearchReplace {
+                    search,
+                    replace: operation.replace.unwrap_or_default(),
+                });
+            }
+            if edits.is_empty() {
+                return Err("edit without search/replace pairs".to_string());
+            }
+            if edits.iter().any(|edit| edit.search.is_empty()) {
+                return Err("empty search text".to_string());
+            }
+            BlockOperation::Edit(edits)
+        }
+        "delete" => BlockOperation::Delete,
+        "rename" | "move" => BlockOperation::Rename(
+            operation
+                .to
+                .filter(|to| !to.trim().is_empty())
+                .ok_or_else(|| "rename without a destination".to_string())?,
+        ),
+        other => return Err(format!("unknown operation `{other}`")),
+    };
+    let symbol = match block_operation {
+        BlockOperation::Write => operation.symbol,
+        _ => None,
+    };
+    Ok(ParsedBlock {
+        target_path: path,
+        symbol,
+        language: operation.language,
+        content,
+        operation: block_operation,
+        start_line: position,
+        end_line: position,
+        marker: PathMarker::Json,
+    })
+}
@@ -0,0 +1,86 @@
+//! Manifest of the files emitted into a prompt, recorded at generation time.
+//! The `Injector` compares it with the current repository contents to detect files
+//! that were edited after the prompt was generated, and uses the stored snapshots
+//! as the base of a three-way merge.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Content hashes of every file included in a generated prompt.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Entries keyed by repository-relative path, using `/` as separator.
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+/// What was recorded about a single file at generation time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Hex-encoded SHA-256 of the file's bytes.
+    pub sha256: String,
+    /// The file's text at generation time; absent for files that are not UTF-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+impl Manifest {
+    /// Returns the manifest path used for a given prompt output file,
+    /// e.g. `prmpt.out` -> `prmpt.out.manifest.json`.
+    pub fn path_for_output(output_file: &str) -> PathBuf {
+        PathBuf::from(format!("{output_file}.manifest.json"))
+    }
+
+    /// Records the current contents of a file under its repository-relative path.
+    pub fn record(&mut self, relative_path: &Path, contents: &[u8]) {
+        self.files.insert(
+            manifest_key(relative_path),
+            ManifestEntry {
+                sha256: sha256_hex(contents),
+                snapshot: String::from_utf8(contents.to_vec()).ok(),
+            },
+        );
+    }
+
+    /// Looks up the entry for a repository-relative path.
+    pub fn get(&self, relative_path: &Path) -> Option<&ManifestEntry> {
+        self.files.get(&manifest_key(relative_path))
+    }
+
+    /// Reads a manifest previously written with [`Manifest::write`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: '{}'", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse manifest: '{}'", path.display()))
+    }
+
+    /// Writes the manifest as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write manifest: '{}'", path.display()))
+    }
+}
+
+/// Normalizes a relative path into a manifest key with `/` separators.
+fn manifest_key(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Hex-encoded SHA-256 digest of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
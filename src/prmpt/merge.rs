@@ -0,0 +1,30 @@
+//! Three-way merge of text files, used when an injection target was edited locally
+//! after the prompt was generated.
+
+use diffy::{ConflictStyle, MergeOptions};
+
+/// Outcome of a three-way merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    /// The merged text, containing conflict markers when `has_conflicts` is set.
+    pub content: String,
+    pub has_conflicts: bool,
+}
+
+/// Merges the local changes (`ours`) and the model's version (`theirs`) of a file
+/// against the snapshot taken at generation time (`base`). Conflicting hunks are
+/// written with standard `<<<<<<< ours` / `=======` / `>>>>>>> theirs` markers.
+pub fn merge_texts(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    let mut options = MergeOptions::new();
+    options.set_conflict_style(ConflictStyle::Merge);
+    match options.merge(base, ours, theirs) {
+        Ok(content) => MergeResult {
+            content,
+            has_conflicts: false,
+        },
+        Err(content) => MergeResult {
+            content,
+            has_conflicts: true,
+        },
+    }
+}
@@ -1,6 +1,23 @@
 pub mod config;
+pub mod file_style;
+pub mod git;
+pub mod hooks;
 pub mod inject_code;
+pub mod json_response;
+pub mod manifest;
+pub mod merge;
+pub mod notebook;
 pub mod parse_python;
+pub mod path_resolution;
+pub mod placeholder;
+pub mod policy;
+pub mod report;
+pub mod response;
+pub mod review;
 pub mod run;
+pub mod symbols;
+pub mod syntax;
 pub mod traits; // Added this line
+pub mod transcript;
 pub mod utils;
+pub mod verify;
@@ -0,0 +1,236 @@
+//! Injection into Jupyter notebooks. Generated prompts render a notebook as its cells,
+//! each introduced by a `// Cell #N (code)` or `// Cell #N (markdown)` line; a block
+//! in that form updates the cells' `source` inside the notebook JSON instead of
+//! overwriting the file, keeping metadata, outputs of untouched cells and cells that
+//! are not rendered (such as raw cells).
+
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+/// A cell as rendered in the prompt.
+#[derive(Debug, PartialEq, Eq)]
+struct RenderedCell {
+    /// The `N` of `// Cell #N`, the cell's index in the notebook it was rendered from
+    index: usize,
+    cell_type: String,
+    source: String,
+}
+
+/// True for `.ipynb` targets.
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "ipynb")
+}
+
+/// True when `content` is a notebook rendered as cells, as generated prompts do.
+pub fn is_cell_render(content: &str) -> bool {
+    content
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| parse_cell_header(line).is_some())
+}
+
+/// Applies a rendered block to `existing` notebook JSON (or a new notebook) and
+/// returns the updated JSON. Rendered cells are matched to existing cells of the
+/// same type, first by identical source, then by number; matched cells keep their
+/// metadata and id, and their outputs unless their source changed. Unmatched
+/// existing code and markdown cells are removed, and unmatched rendered cells are
+/// added. `Err` carries a skip reason.
+pub fn update_notebook(existing: Option<&str>, rendered: &str) -> Result<String, String> {
+    let mut notebook: Value = match existing {
+        Some(existing) => serde_json::from_str(existing)
+            .map_err(|e| format!("target is not a valid notebook: {e}"))?,
+        None => json!({
+            "cells": [],
+            "metadata": {},
+            "nbformat": 4,
+            "nbformat_minor": 5,
+        }),
+    };
+    let uses_ids = notebook
+        .get("nbformat_minor")
+        .and_then(Value::as_u64)
+        .is_some_and(|minor| minor >= 5);
+    let Some(cells) = notebook.get_mut("cells").and_then(Value::as_array_mut) else {
+        return Err("target notebook has no cells array".to_string());
+    };
+    let original = std::mem::take(cells);
+    let rendered = parse_cells(rendered);
+
+    // Which original cell each rendered cell reuses
+    let mut used = vec![false; original.len()];
+    let mut matches: Vec<Option<usize>> = vec![None; rendered.len()];
+    for (cell, slot) in rendered.iter().zip(matches.iter_mut()) {
+        let same_source = |j: usize| {
+            !used[j]
+                && is_rendered_type(&original[j])
+                && cell_type(&original[j]) == Some(&cell.cell_type)
+                && cell_source(&original[j]) == cell.source
+        };
+        let found = if cell.index < original.len() && same_source(cell.index) {
+            Some(cell.index)
+        } else {
+            (0..original.len()).find(|&j| same_source(j))
+        };
+        if let Some(j) = found {
+            used[j] = true;
+            *slot = Some(j);
+        }
+    }
+    for (cell, slot) in rendered.iter().zip(matches.iter_mut()) {
+        let j = cell.index;
+        if slot.is_none()
+            && j < original.len()
+            && !used[j]
+            && is_rendered_type(&original[j])
+            && cell_type(&original[j]) == Some(&cell.cell_type)
+        {
+            used[j] = true;
+            *slot = Some(j);
+        }
+    }
+
+    // Cells that were never rendered stay after the cell they followed
+    let mut kept = vec![false; original.len()];
+    let mut new_cells = Vec::new();
+    let keep_unrendered_after = |start: usize, new_cells: &mut Vec<Value>, kept: &mut Vec<bool>| {
+        for j in start..original.len() {
+            if is_rendered_type(&original[j]) {
+                break;
+            }
+            if !kept[j] {
+                kept[j] = true;
+                new_cells.push(original[j].clone());
+            }
+        }
+    };
+    keep_unrendered_after(0, &mut new_cells, &mut kept);
+    for (cell, slot) in rendered.iter().zip(&matches) {
+        match slot {
+            Some(j) => {
+                let mut reused = original[*j].clone();
+                if cell_source(&reused) != cell.source {
+                    set_source(&mut reused, &cell.source);
+                    if cell.cell_type == "code" {
+                        reused["outputs"] = json!([]);
+                        reused["execution_count"] = Value::Null;
+                    }
+                }
+                new_cells.push(reused);
+                keep_unrendered_after(j + 1, &mut new_cells, &mut kept);
+            }
+            None => new_cells.push(new_cell(cell, uses_ids)),
+        }
+    }
+    for (j, cell) in original.iter().enumerate() {
+        if !kept[j] && !is_rendered_type(cell) {
+            new_cells.push(cell.clone());
+        }
+    }
+    notebook["cells"] = Value::Array(new_cells);
+
+    // Jupyter's own layout: one-space indentation, non-ASCII kept as is
+    let mut output = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut output, formatter);
+    notebook
+        .serialize(&mut serializer)
+        .map_err(|e| format!("failed to serialize notebook: {e}"))?;
+    let mut output = String::from_utf8(output).map_err(|e| e.to_string())?;
+    output.push('\n');
+    Ok(output)
+}
+
+/// Parses a `// Cell #N (type)` header into the cell number and type.
+fn parse_cell_header(line: &str) -> Option<(usize, &str)> {
+    let rest = line.trim().strip_prefix("// Cell #")?;
+    let (number, kind) = rest.split_once(' ')?;
+    let kind = kind.strip_prefix('(')?.strip_suffix(')')?;
+    matches!(kind, "code" | "markdown" | "outputs").then_some((number.parse().ok()?, kind))
+}
+
+/// Splits a rendered notebook into its cells. Output sections are dropped: outputs
+/// are kept from the notebook, not from the response.
+fn parse_cells(rendered: &str) -> Vec<RenderedCell> {
+    let mut cells: Vec<RenderedCell> = Vec::new();
+    let mut in_outputs = false;
+    for line in rendered.split_inclusive('\n') {
+        if let Some((index, kind)) = parse_cell_header(line) {
+            in_outputs = kind == "outputs";
+            if !in_outputs {
+                cells.push(RenderedCell {
+                    index,
+                    cell_type: kind.to_string(),
+                    source: String::new(),
+                });
+            }
+        } else if let (false, Some(cell)) = (in_outputs, cells.last_mut()) {
+            cell.source.push_str(line);
+        }
+    }
+    // Generation ends every cell with a line break of its own
+    for cell in &mut cells {
+        if cell.source.ends_with('\n') {
+            cell.source.pop();
+            if cell.source.ends_with('\r') {
+                cell.source.pop();
+            }
+        }
+    }
+    cells
+}
+
+fn cell_type(cell: &Value) -> Option<&String> {
+    match cell.get("cell_type") {
+        Some(Value::String(cell_type)) => Some(cell_type),
+        _ => None,
+    }
+}
+
+/// Whether generation renders this cell: code and markdown cells with a source array.
+fn is_rendered_type(cell: &Value) -> bool {
+    matches!(
+        cell_type(cell).map(String::as_str),
+        Some("code" | "markdown")
+    ) && cell.get("source").is_some_and(Value::is_array)
+}
+
+fn cell_source(cell: &Value) -> String {
+    match cell.get("source") {
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        Some(Value::String(source)) => source.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Stores `source` in nbformat's layout: a list of lines, each but the last ending
+/// in a line break.
+fn set_source(cell: &mut Value, source: &str) {
+    cell["source"] = source
+        .split_inclusive('\n')
+        .map(|line| Value::String(line.to_string()))
+        .collect();
+}
+
+fn new_cell(cell: &RenderedCell, uses_ids: bool) -> Value {
+    let mut value = Value::Object(Map::new());
+    value["cell_type"] = json!(cell.cell_type);
+    value["metadata"] = json!({});
+    if cell.cell_type == "code" {
+        value["execution_count"] = Value::Null;
+        value["outputs"] = json!([]);
+    }
+    if uses_ids {
+        let id: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect();
+        value["id"] = json!(id);
+    }
+    set_source(&mut value, &cell.source);
+    value
+}
@@ -1,29 +1,166 @@
 //! Implements functionality for parsing Python code using Tree-sitter in order
-//! to extract function/class signatures and docstrings.
+//! to extract function/class signatures, docstrings and comments, or `.pyi` stubs.
 
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use std::fs;
-use tree_sitter::{Node, Parser};
+use std::{collections::HashSet, fs, str::FromStr};
+use tree_sitter::{Node, Parser, Tree};
 use tree_sitter_python;
 
+/// Calls whose result marks a class attribute as an attrs or dataclass field.
+const FIELD_CALLS: &[&str] = &[
+    "attr.ib",
+    "attr.attrib",
+    "attr.field",
+    "attrs.field",
+    "attrib",
+    "field",
+    "dataclasses.field",
+];
+
+/// Calls that define a type variable or a new type, kept with module attributes.
+const TYPE_DEFINING_CALLS: &[&str] = &["TypeVar", "ParamSpec", "TypeVarTuple", "NewType"];
+
+/// Words that make a comment a marker comment, when they start it.
+const COMMENT_MARKERS: &[&str] = &["TODO", "FIXME", "NOTE", "XXX", "HACK", "BUG"];
+
+/// Comments that only instruct tools and are never kept.
+const PRAGMA_PREFIXES: &[&str] = &[
+    "#!",
+    "# -*-",
+    "# type:",
+    "# noqa",
+    "# pylint:",
+    "# fmt:",
+    "# pragma",
+    "# mypy:",
+    "# isort:",
+];
+
+/// Kinds of `#` comments kept in docs-only output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentKind {
+    /// Comments at the top of a module, before its first statement
+    Header,
+    /// Runs of comments directly above a kept definition or attribute
+    Block,
+    /// `# TODO`, `# FIXME`, `# NOTE`, `# XXX`, `# HACK` and `# BUG` comments
+    /// anywhere, kept under the enclosing definition
+    Marker,
+}
+
+impl CommentKind {
+    pub const ALL: &'static [CommentKind] =
+        &[CommentKind::Header, CommentKind::Block, CommentKind::Marker];
+}
+
+impl FromStr for CommentKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "header" => Ok(CommentKind::Header),
+            "block" => Ok(CommentKind::Block),
+            "marker" => Ok(CommentKind::Marker),
+            other => Err(format!(
+                "unknown comment kind `{other}` (expected header, block or marker)"
+            )),
+        }
+    }
+}
+
+/// What an extracted item is, which decides the blank lines around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Definition,
+    Statement,
+    Header,
+    Comment,
+}
+
+/// Where a statement sits, which decides what is kept from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Module,
+    Class,
+    Function,
+}
+
+/// Settings of one extraction, shared by the functions walking the tree.
+struct Extraction<'a> {
+    source_code: &'a str,
+    comments: &'a [CommentKind],
+    /// Render a `.pyi` stub: bodies end in `...`, values of typed attributes are
+    /// elided and imports are kept
+    stub: bool,
+    /// Names a stub refers to; imports binding other names are dropped
+    used_names: HashSet<String>,
+}
+
 /// Sets up a Tree-sitter parser for Python and extracts function/class signatures
 /// along with docstrings from the provided file contents.
 ///
+/// Signatures are copied from the source as written, with decorators, `async`,
+/// type parameters, class bases and return annotations. Module-level `__all__`,
+/// type aliases and annotated constants, class fields (annotated or attrs-style)
+/// and attributes followed by a docstring are kept as well. Comments are dropped;
+/// see [`extract_python_signatures_with_comments`] to keep them.
+///
 /// This is used in "docs-only" modes, or to produce more descriptive prompts.
 pub fn extract_python_signatures(contents: &str) -> String {
+    extract_python_signatures_with_comments(contents, &[])
+}
+
+/// Like [`extract_python_signatures`], also keeping the `#` comments of the given
+/// kinds.
+pub fn extract_python_signatures_with_comments(contents: &str, comments: &[CommentKind]) -> String {
+    let extraction = Extraction {
+        source_code: contents,
+        comments,
+        stub: false,
+        used_names: HashSet::new(),
+    };
+    extract_module(&parse_python(contents), &extraction)
+}
+
+/// Turns a Python module into a `.pyi`-style stub: the signatures, docstrings,
+/// attributes and comments of [`extract_python_signatures_with_comments`], with `...` bodies,
+/// typed attributes without their values, and the imports the stub refers to.
+/// Definitions nested in functions are left out.
+pub fn extract_python_stub(contents: &str, comments: &[CommentKind]) -> String {
+    let tree = parse_python(contents);
+    let mut extraction = Extraction {
+        source_code: contents,
+        comments,
+        stub: true,
+        used_names: HashSet::new(),
+    };
+    // A first pass without imports finds the names the stub needs
+    let draft = extract_module(&tree, &extraction);
+    collect_identifiers(
+        parse_python(&draft).root_node(),
+        &draft,
+        &mut extraction.used_names,
+    );
+    extract_module(&tree, &extraction)
+}
+
+fn parse_python(contents: &str) -> Tree {
     let mut parser = Parser::new();
     let language = tree_sitter_python::LANGUAGE.into();
     parser
         .set_language(&language)
         .expect("Error loading Python grammar");
+    parser.parse(contents, None).unwrap()
+}
 
-    let tree = parser.parse(contents, None).unwrap();
+fn extract_module(tree: &Tree, extraction: &Extraction) -> String {
     let root_node = tree.root_node();
-
     let mut signatures = String::new();
 
     // Before processing other nodes, check for module-level docstring
-    let module_docstring = extract_module_docstring(root_node, contents);
+    let module_docstring = extract_module_docstring(root_node, extraction.source_code);
     if !module_docstring.is_empty() {
         signatures.push_str(&module_docstring);
         signatures.push('\n');
@@ -31,12 +168,14 @@ pub fn extract_python_signatures(contents: &str) -> String {
 
     // Process definitions starting from the root node
     let mut cursor = root_node.walk();
-    for child in root_node.children(&mut cursor) {
-        let child_signatures = extract_definitions(child, contents, 0);
-        if !child_signatures.is_empty() {
-            signatures.push_str(&child_signatures);
+    let statements = root_node.named_children(&mut cursor).collect();
+    let (items, _) = extract_body(statements, extraction, Scope::Module, 0);
+    if !items.is_empty() {
+        if !signatures.is_empty() {
             signatures.push('\n');
         }
+        signatures.push_str(&items);
+        signatures.push('\n');
     }
 
     signatures
@@ -64,88 +203,565 @@ fn extract_module_docstring(root_node: Node, source_code: &str) -> String {
     String::new()
 }
 
-/// Recursively extracts function and class signatures from a given AST node.
-fn extract_definitions(node: Node, source_code: &str, indent_level: usize) -> String {
-    let mut output = String::new();
-
-    if node.kind() == "function_definition" || node.kind() == "class_definition" {
-        let signature = extract_signature_and_docstring(node, source_code, indent_level);
-        output.push_str(&signature);
-        output.push('\n');
-    }
+/// Extracts the kept statements of a module or block, with the comments of the
+/// requested kinds. Definitions and the module header are separated from their
+/// neighbours by a blank line, runs of attributes and comments are not. Also
+/// returns whether any statement (rather than only comments) was kept.
+fn extract_body(
+    statements: Vec<Node>,
+    extraction: &Extraction,
+    scope: Scope,
+    indent_level: usize,
+) -> (String, bool) {
+    let Extraction {
+        source_code,
+        comments,
+        ..
+    } = *extraction;
+    let indent = "    ".repeat(indent_level);
+    let mut items: Vec<(Item, String)> = Vec::new();
+    // Own-line comments not yet known to belong to the next statement
+    let mut pending: Vec<Node> = Vec::new();
+    // Header comments are those before the module's first statement
+    let mut in_header = scope == Scope::Module;
+    let loose = |comment: Node, in_header: bool, items: &mut Vec<(Item, String)>| {
+        let text = comment_text(comment, source_code);
+        let item = if in_header && comments.contains(&CommentKind::Header) {
+            Item::Header
+        } else if comments.contains(&CommentKind::Marker) && is_marker(text) {
+            Item::Comment
+        } else {
+            return;
+        };
+        if !is_pragma(text) {
+            items.push((item, format!("{indent}{text}")));
+        }
+    };
 
-    // If this is a class or function, process its body to find nested definitions
-    if node.kind() == "class_definition" || node.kind() == "function_definition" {
-        // Find the block node (the body)
-        let mut cursor = node.walk();
-        for child in node.children(&mut cursor) {
-            if child.kind() == "block" {
-                // Process the statements inside the block
-                let mut block_cursor = child.walk();
-                for stmt in child.children(&mut block_cursor) {
-                    let stmt_output = extract_definitions(stmt, source_code, indent_level + 1);
-                    if !stmt_output.is_empty() {
-                        output.push_str(&stmt_output);
-                        output.push('\n');
+    for stmt in statements {
+        if stmt.kind() == "comment" {
+            if starts_line(stmt, source_code) {
+                pending.push(stmt);
+            } else {
+                // A trailing comment, after code on the same line
+                for comment in pending.drain(..) {
+                    loose(comment, in_header, &mut items);
+                }
+                loose(stmt, in_header, &mut items);
+            }
+            continue;
+        }
+        let kind = if definition_of(stmt).is_some() {
+            Item::Definition
+        } else {
+            Item::Statement
+        };
+        let stmt_output = extract_definitions(stmt, extraction, scope, indent_level);
+        if stmt_output.is_empty() {
+            for comment in pending.drain(..) {
+                loose(comment, in_header, &mut items);
+            }
+            if in_header && is_string_statement(stmt) {
+                // The module docstring, already extracted
+                continue;
+            }
+            in_header = false;
+            if comments.contains(&CommentKind::Marker) {
+                let mut markers = Vec::new();
+                collect_comments(stmt, &mut markers);
+                for marker in markers {
+                    let text = comment_text(marker, source_code);
+                    if is_marker(text) {
+                        items.push((Item::Comment, format!("{indent}{text}")));
                     }
                 }
             }
+            continue;
+        }
+
+        // The comments right above the statement, without blank lines in between
+        let mut attached = pending.len();
+        let mut row = stmt.start_position().row;
+        while attached > 0 && pending[attached - 1].end_position().row + 1 == row {
+            attached -= 1;
+            row = pending[attached].start_position().row;
+        }
+        let block = pending.split_off(attached);
+        for comment in pending.drain(..) {
+            loose(comment, in_header, &mut items);
         }
+        in_header = false;
+        let mut item = String::new();
+        for comment in block {
+            let text = comment_text(comment, source_code);
+            if !comments.contains(&CommentKind::Block) {
+                loose(comment, false, &mut items);
+            } else if !is_pragma(text) {
+                item.push_str(&format!("{indent}{text}\n"));
+            }
+        }
+        item.push_str(&stmt_output);
+        items.push((kind, item));
+    }
+    for comment in pending {
+        loose(comment, in_header, &mut items);
     }
 
-    output
+    let has_statements = items
+        .iter()
+        .any(|(kind, _)| matches!(kind, Item::Definition | Item::Statement));
+    let mut output = String::new();
+    let mut previous = None;
+    for (kind, item) in items {
+        if let Some(previous) = previous {
+            output.push('\n');
+            if kind == Item::Definition
+                || previous == Item::Definition
+                || (previous == Item::Header && kind != Item::Header)
+            {
+                output.push('\n');
+            }
+        }
+        output.push_str(&item);
+        previous = Some(kind);
+    }
+    (output, has_statements)
 }
 
-/// Extracts the signature (including decorators) and docstring from a function or class node.
-fn extract_signature_and_docstring(node: Node, source_code: &str, indent_level: usize) -> String {
-    let mut signature = String::new();
-    let indent = "    ".repeat(indent_level);
-
-    if node.kind() == "function_definition" || node.kind() == "class_definition" {
-        signature.push_str(&indent);
-
-        let mut cursor = node.walk();
-        let children = node.children(&mut cursor);
-        let mut found_signature = false;
-        for child in children {
-            match child.kind() {
-                "decorator" => {
-                    let decorator_text = child.utf8_text(source_code.as_bytes()).unwrap();
-                    signature.push_str(&indent);
-                    signature.push_str(decorator_text);
-                    signature.push('\n');
-                }
-                "def" | "class" => {
-                    let text = child.utf8_text(source_code.as_bytes()).unwrap();
-                    signature.push_str(text);
-                    signature.push(' ');
-                }
-                "identifier" | "parameters" | ":" => {
-                    let text = child.utf8_text(source_code.as_bytes()).unwrap();
-                    signature.push_str(text);
-                    if child.kind() == ":" {
-                        signature.push('\n');
-                    }
-                    found_signature = true;
-                }
-                "block" => {
-                    // After the signature, look for the docstring
-                    if found_signature {
-                        let docstring = extract_docstring(child, source_code, indent_level + 1);
-                        if !docstring.is_empty() {
-                            signature.push_str(&docstring);
+/// Extracts what docs-only output keeps of one statement: a definition with its
+/// nested definitions, or (outside functions) an attribute, and for stubs the
+/// module's imports.
+fn extract_definitions(
+    node: Node,
+    extraction: &Extraction,
+    scope: Scope,
+    indent_level: usize,
+) -> String {
+    let source_code = extraction.source_code;
+    if let Some(definition) = definition_of(node) {
+        if extraction.stub && scope == Scope::Function {
+            return String::new();
+        }
+        let mut output =
+            extract_signature_and_docstring(definition, node, source_code, indent_level);
+        // Process the body to find nested definitions
+        if let Some(body) = definition.child_by_field_name("body") {
+            let nested_scope = if definition.kind() == "class_definition" {
+                Scope::Class
+            } else {
+                Scope::Function
+            };
+            // Tree-sitter attaches comments between the colon and the first
+            // statement to the definition rather than to its body
+            let mut cursor = definition.walk();
+            let mut statements: Vec<Node> = definition
+                .children(&mut cursor)
+                .filter(|child| child.kind() == "comment")
+                .collect();
+            let mut cursor = body.walk();
+            statements.extend(body.named_children(&mut cursor));
+            let (nested, has_statements) =
+                extract_body(statements, extraction, nested_scope, indent_level + 1);
+            if !nested.is_empty() {
+                output.push('\n');
+                output.push_str(&nested);
+            }
+            let has_docstring = body.named_child(0).is_some_and(is_string_statement);
+            if extraction.stub && !has_docstring && !has_statements {
+                output.push_str(&format!("\n{}...", "    ".repeat(indent_level + 1)));
+            }
+        }
+        return output;
+    }
+    if scope == Scope::Function {
+        return String::new();
+    }
+    match node.kind() {
+        "type_alias_statement" => reindent(node, source_code, indent_level),
+        "expression_statement" => extract_attribute(node, extraction, scope, indent_level),
+        "import_statement" | "import_from_statement" | "future_import_statement"
+            if extraction.stub && scope == Scope::Module =>
+        {
+            extract_import(node, extraction)
+        }
+        // Imports only needed for annotations are plain imports in a stub
+        "if_statement"
+            if extraction.stub
+                && scope == Scope::Module
+                && node
+                    .child_by_field_name("condition")
+                    .and_then(|condition| condition.utf8_text(source_code.as_bytes()).ok())
+                    .is_some_and(|condition| condition.ends_with("TYPE_CHECKING")) =>
+        {
+            let Some(consequence) = node.child_by_field_name("consequence") else {
+                return String::new();
+            };
+            extract_block_imports(consequence, extraction).join("\n")
+        }
+        // Optional dependencies: `try: import numpy as np` with fallbacks in handlers
+        "try_statement" if extraction.stub && scope == Scope::Module => {
+            let mut imports = Vec::new();
+            if let Some(body) = node.child_by_field_name("body") {
+                imports.extend(extract_block_imports(body, extraction));
+            }
+            let mut cursor = node.walk();
+            for clause in node.named_children(&mut cursor) {
+                let mut clause_cursor = clause.walk();
+                for block in clause
+                    .named_children(&mut clause_cursor)
+                    .filter(|child| child.kind() == "block")
+                {
+                    for import in extract_block_imports(block, extraction) {
+                        if !imports.contains(&import) {
+                            imports.push(import);
                         }
-                        break; // We only need the first block
                     }
                 }
-                _ => {}
             }
+            imports.join("\n")
         }
+        _ => String::new(),
+    }
+}
+
+/// The stub imports of the import statements directly inside `block`.
+fn extract_block_imports(block: Node, extraction: &Extraction) -> Vec<String> {
+    let mut cursor = block.walk();
+    block
+        .named_children(&mut cursor)
+        .filter(|stmt| {
+            stmt.kind().starts_with("import") || stmt.kind() == "future_import_statement"
+        })
+        .map(|stmt| extract_import(stmt, extraction))
+        .filter(|import| !import.is_empty())
+        .collect()
+}
+
+/// Renders an import for a stub with only the names the stub uses, or nothing
+/// when it uses none of them. Wildcard and `__future__` imports are kept.
+fn extract_import(node: Node, extraction: &Extraction) -> String {
+    let text = |node: Node| {
+        node.utf8_text(extraction.source_code.as_bytes())
+            .unwrap_or_default()
+    };
+    let mut cursor = node.walk();
+    if node.kind() == "future_import_statement"
+        || node
+            .children(&mut cursor)
+            .any(|child| child.kind() == "wildcard_import")
+    {
+        return text(node).to_string();
+    }
+    let mut cursor = node.walk();
+    let names: Vec<&str> = node
+        .children_by_field_name("name", &mut cursor)
+        .filter(|name| {
+            // The name an import binds: its alias, or the first part of a dotted name
+            let bound = match name.child_by_field_name("alias") {
+                Some(alias) => text(alias),
+                None => text(*name).split('.').next().unwrap_or_default(),
+            };
+            extraction.used_names.contains(bound.trim())
+        })
+        .map(text)
+        .collect();
+    if names.is_empty() {
+        return String::new();
+    }
+    match node.child_by_field_name("module_name") {
+        Some(module) => format!("from {} import {}", text(module), names.join(", ")),
+        None => format!("import {}", names.join(", ")),
+    }
+}
+
+/// Collects the identifiers used anywhere inside `node`, including those in string
+/// annotations such as `"Iterator[int]"`.
+fn collect_identifiers(node: Node, source_code: &str, names: &mut HashSet<String>) {
+    collect_identifiers_in(node, source_code, names, false);
+}
+
+fn collect_identifiers_in(
+    node: Node,
+    source_code: &str,
+    names: &mut HashSet<String>,
+    in_annotation: bool,
+) {
+    match node.kind() {
+        "identifier" => {
+            if let Ok(name) = node.utf8_text(source_code.as_bytes()) {
+                names.insert(name.to_string());
+            }
+            return;
+        }
+        // A forward reference is an annotation written as a string
+        "string" if in_annotation => {
+            let text = node.utf8_text(source_code.as_bytes()).unwrap_or_default();
+            let (annotation, _) = strip_quotes(text);
+            collect_identifiers_in(
+                parse_python(annotation).root_node(),
+                annotation,
+                names,
+                false,
+            );
+            return;
+        }
+        _ => {}
+    }
+    let in_annotation = in_annotation || node.kind() == "type";
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_identifiers_in(child, source_code, names, in_annotation);
+    }
+}
+
+/// A comment's text without trailing whitespace.
+fn comment_text<'a>(comment: Node, source_code: &'a str) -> &'a str {
+    comment
+        .utf8_text(source_code.as_bytes())
+        .unwrap_or_default()
+        .trim_end()
+}
+
+/// Whether a node starts its line, as opposed to trailing code.
+fn starts_line(node: Node, source_code: &str) -> bool {
+    let line_start = source_code[..node.start_byte()]
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    source_code[line_start..node.start_byte()].trim().is_empty()
+}
+
+/// Whether a comment starts with a marker word such as `TODO`.
+fn is_marker(text: &str) -> bool {
+    let text = text.trim_start_matches('#').trim_start();
+    COMMENT_MARKERS.iter().any(|marker| {
+        text.strip_prefix(marker)
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+    })
+}
+
+fn is_pragma(text: &str) -> bool {
+    PRAGMA_PREFIXES
+        .iter()
+        .any(|prefix| text.starts_with(prefix))
+}
+
+/// Whether a statement is a lone string, i.e. a docstring.
+fn is_string_statement(node: Node) -> bool {
+    node.kind() == "expression_statement"
+        && node.named_child_count() == 1
+        && node
+            .named_child(0)
+            .is_some_and(|child| child.kind() == "string")
+}
+
+/// Collects the comments anywhere inside `node`, in source order.
+fn collect_comments<'t>(node: Node<'t>, out: &mut Vec<Node<'t>>) {
+    if node.kind() == "comment" {
+        out.push(node);
+        return;
     }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_comments(child, out);
+    }
+}
+
+/// The function or class defined by a statement, looking through decorators.
+fn definition_of(node: Node) -> Option<Node> {
+    match node.kind() {
+        "function_definition" | "class_definition" => Some(node),
+        "decorated_definition" => node.child_by_field_name("definition"),
+        _ => None,
+    }
+}
 
+/// Extracts the signature (including decorators) and docstring from a function or
+/// class node. `statement` is the node the definition starts at, its decorated
+/// definition when it has decorators. The signature is the source up to the colon
+/// before the body, re-indented to `indent_level`.
+fn extract_signature_and_docstring(
+    definition: Node,
+    statement: Node,
+    source_code: &str,
+    indent_level: usize,
+) -> String {
+    let body = definition.child_by_field_name("body");
+    let mut cursor = definition.walk();
+    let colon = definition
+        .children(&mut cursor)
+        .take_while(|child| Some(*child) != body)
+        .filter(|child| child.kind() == ":")
+        .last();
+    let end = colon.map_or(definition.end_byte(), |colon| colon.end_byte());
+    let text = &source_code[statement.start_byte()..end];
+    let mut signature = reindent_text(
+        text,
+        statement.start_position().column,
+        &"    ".repeat(indent_level),
+    );
+
+    // After the signature, look for the docstring
+    if let Some(body) = body {
+        let docstring = extract_docstring(body, source_code, indent_level + 1);
+        if !docstring.is_empty() {
+            signature.push('\n');
+            signature.push_str(&docstring);
+        }
+    }
     signature
 }
 
+/// Extracts an attribute kept in docs-only output: `__all__`, type variables,
+/// type aliases and annotated constants of a module, annotated and attrs-style
+/// fields of a class, and any attribute followed by an attribute docstring. Values
+/// spanning several lines, and in stubs the values of typed attributes, are
+/// shortened to `...`, except for `__all__`, type variables and aliases.
+fn extract_attribute(
+    node: Node,
+    extraction: &Extraction,
+    scope: Scope,
+    indent_level: usize,
+) -> String {
+    let source_code = extraction.source_code;
+    let Some(assignment) = node
+        .named_child(0)
+        .filter(|child| matches!(child.kind(), "assignment" | "augmented_assignment"))
+    else {
+        return String::new();
+    };
+    let text = |node: Node| node.utf8_text(source_code.as_bytes()).unwrap_or_default();
+    let left = assignment.child_by_field_name("left");
+    let annotation = assignment.child_by_field_name("type");
+    let value = assignment.child_by_field_name("right");
+
+    let is_all = scope == Scope::Module && left.is_some_and(|left| text(left) == "__all__");
+    let is_field = scope == Scope::Class
+        && value.is_some_and(|value| {
+            value.kind() == "call"
+                && value
+                    .child_by_field_name("function")
+                    .is_some_and(|function| FIELD_CALLS.contains(&text(function)))
+        });
+    let is_type = scope == Scope::Module
+        && annotation.is_none()
+        && value.is_some_and(|value| defines_type(value, source_code));
+    let docstring = attribute_docstring(node);
+    if !(is_all || is_type || annotation.is_some() || is_field || docstring.is_some()) {
+        return String::new();
+    }
+
+    let indent = "    ".repeat(indent_level);
+    let shorten = text(assignment).contains('\n') || (extraction.stub && annotation.is_some());
+    let mut output = if is_all || is_type || !shorten {
+        reindent(node, source_code, indent_level)
+    } else {
+        let mut shortened = format!("{indent}{}", left.map(text).unwrap_or_default());
+        if let Some(annotation) = annotation {
+            shortened.push_str(&format!(": {}", text(annotation).replace('\n', " ")));
+        }
+        if value.is_some() {
+            shortened.push_str(" = ...");
+        }
+        shortened
+    };
+    if let Some(docstring) = docstring {
+        let (stripped, quote_type) = strip_quotes(text(docstring));
+        output.push('\n');
+        output.push_str(&indent_docstring(stripped, &indent, quote_type));
+    }
+    output
+}
+
+/// Whether an assigned value defines a type: a `TypeVar`, `ParamSpec`,
+/// `TypeVarTuple` or `NewType` call, or a type expression such as `list[int]`,
+/// `int | None` or a capitalized name, which makes the assignment a type alias.
+fn defines_type(value: Node, source_code: &str) -> bool {
+    let text = |node: Node| node.utf8_text(source_code.as_bytes()).unwrap_or_default();
+    match value.kind() {
+        "call" => value
+            .child_by_field_name("function")
+            .is_some_and(|function| {
+                let name = text(function);
+                let name = name.rsplit('.').next().unwrap_or(name);
+                TYPE_DEFINING_CALLS.contains(&name)
+            }),
+        "subscript" => value
+            .child_by_field_name("value")
+            .is_some_and(|base| defines_type(base, source_code)),
+        "binary_operator" => {
+            value
+                .child_by_field_name("operator")
+                .is_some_and(|operator| text(operator) == "|")
+                && value
+                    .child_by_field_name("left")
+                    .is_some_and(|left| defines_type(left, source_code))
+                && value
+                    .child_by_field_name("right")
+                    .is_some_and(|right| defines_type(right, source_code))
+        }
+        "none" => true,
+        // `Optional`, `typing.Any`: capitalized, but not an ALL_CAPS constant
+        "identifier" | "attribute" => {
+            let name = text(value);
+            let name = name.rsplit('.').next().unwrap_or(name);
+            (name.starts_with(|c: char| c.is_ascii_uppercase())
+                && name.chars().any(|c| c.is_ascii_lowercase()))
+                || matches!(
+                    name,
+                    "int"
+                        | "str"
+                        | "float"
+                        | "bool"
+                        | "bytes"
+                        | "complex"
+                        | "object"
+                        | "list"
+                        | "dict"
+                        | "set"
+                        | "frozenset"
+                        | "tuple"
+                        | "type"
+                )
+        }
+        _ => false,
+    }
+}
+
+/// The string statement directly following an attribute assignment (PEP 257's
+/// attribute docstring), if any.
+fn attribute_docstring(statement: Node) -> Option<Node> {
+    let next = statement.next_named_sibling()?;
+    is_string_statement(next).then(|| next.named_child(0))?
+}
+
+/// The source of `node` re-indented to `indent_level`.
+fn reindent(node: Node, source_code: &str, indent_level: usize) -> String {
+    let text = node.utf8_text(source_code.as_bytes()).unwrap_or_default();
+    reindent_text(
+        text,
+        node.start_position().column,
+        &"    ".repeat(indent_level),
+    )
+}
+
+/// Re-indents source text that started at `column`: continuation lines lose up to
+/// `column` leading spaces, and every line gets `indent`.
+fn reindent_text(text: &str, column: usize, indent: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let line = if i == 0 {
+                line
+            } else {
+                let leading = line.len() - line.trim_start().len();
+                &line[leading.min(column)..]
+            };
+            format!("{indent}{line}").trim_end().to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 /// Attempts to extract a docstring from a code block (e.g., the first statement in a function).
 fn extract_docstring(block_node: Node, source_code: &str, indent_level: usize) -> String {
     let mut cursor = block_node.walk();
@@ -224,10 +840,9 @@ fn indent_docstring(docstring: &str, indent: &str, quote_type: &str) -> String {
 fn dedent(s: &str) -> String {
     let lines: Vec<&str> = s.lines().collect();
     // remove first index if it is empty or whitespace-only
-    let lines = if lines.first().is_none_or(|line| line.trim().is_empty()) {
-        &lines[1..]
-    } else {
-        &lines
+    let lines = match lines.split_first() {
+        Some((first, rest)) if first.trim().is_empty() => rest,
+        _ => &lines[..],
     };
 
     // remove all whitespace from the beginning of each line
@@ -0,0 +1,195 @@
+//! Resolves the paths models write in their answers to files in the repository.
+//! Generated prompts start the tree with the repository's directory name, so answers
+//! often use `myrepo/src/lib.rs`, `./src/lib.rs`, made-up absolute paths or a bare
+//! `lib.rs`. The resolver normalizes these and maps them to a unique existing file
+//! by path suffix, only treating a path as new when it clearly is.
+
+use ignore::WalkBuilder;
+use std::{
+    cell::OnceCell,
+    path::{Component, Path, PathBuf},
+};
+
+/// Outcome of resolving a target path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolvedPath {
+    /// An existing file, as a `/`-separated path relative to the repository.
+    Existing(String),
+    /// A file that does not exist yet (or a path left for the security check to
+    /// reject, such as one escaping the repository).
+    New(String),
+    /// Several existing files match; carries their relative paths.
+    Ambiguous(Vec<String>),
+}
+
+/// Resolves target paths against one repository, listing its files on first use.
+pub struct PathResolver {
+    base_path_canon: PathBuf,
+    repo_name: Option<String>,
+    files: OnceCell<Vec<String>>,
+}
+
+impl PathResolver {
+    pub fn new(base_path_canon: &Path) -> Self {
+        Self {
+            base_path_canon: base_path_canon.to_path_buf(),
+            repo_name: base_path_canon
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            files: OnceCell::new(),
+        }
+    }
+
+    /// Lexical clean-up only: separators, `./`, a leading repository name, and
+    /// absolute paths inside the repository. Used for paths that are meant to be new,
+    /// such as rename destinations.
+    pub fn normalize(&self, target: &str) -> String {
+        let target = target.trim().replace('\\', "/");
+        let path = Path::new(&target);
+        let relative = if path.is_absolute() {
+            match path.strip_prefix(&self.base_path_canon) {
+                Ok(inside) => inside.to_string_lossy().replace('\\', "/"),
+                // Left absolute so the repository check rejects it
+                Err(_) => return target,
+            }
+        } else {
+            target
+        };
+
+        let mut components: Vec<&str> = relative
+            .split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .collect();
+        if components.len() > 1
+            && Some(components[0]) == self.repo_name.as_deref()
+            && !self.base_path_canon.join(components[0]).is_dir()
+        {
+            components.remove(0);
+        }
+        components.join("/")
+    }
+
+    /// Resolves `target` to an existing file when it can be identified unambiguously.
+    /// A path is new when its parent directory exists (e.g. `tests/new_test.rs`), or
+    /// when no existing file ends with it; a path with directories never matches an
+    /// existing file by its file name alone.
+    pub fn resolve(&self, target: &str) -> ResolvedPath {
+        let normalized = self.normalize(target);
+        let is_absolute = Path::new(&normalized).is_absolute();
+        let escapes = Path::new(&normalized)
+            .components()
+            .any(|c| c == Component::ParentDir);
+        if normalized.is_empty() || escapes {
+            return ResolvedPath::New(normalized);
+        }
+        if !is_absolute && self.base_path_canon.join(&normalized).exists() {
+            return ResolvedPath::Existing(normalized);
+        }
+
+        let components: Vec<&str> = normalized.split('/').filter(|c| !c.is_empty()).collect();
+        let parent_exists = components.len() > 1
+            && !is_absolute
+            && self
+                .base_path_canon
+                .join(components[..components.len() - 1].join("/"))
+                .is_dir();
+        if parent_exists {
+            return ResolvedPath::New(normalized);
+        }
+
+        // Longest trailing part first. A relative path keeps every directory the model
+        // gave (`src/lib.rs` may match `crates/a/src/lib.rs`); only a made-up absolute
+        // prefix is dropped, and only a bare file name matches by file name alone.
+        let kept = match components.len() {
+            1 => 1,
+            len if is_absolute => 2.min(len),
+            len => len,
+        };
+        let files = self.files();
+        for start in 0..=components.len() - kept {
+            let suffix = components[start..].join("/");
+            let slash_suffix = format!("/{suffix}");
+            let matches: Vec<&String> = files
+                .iter()
+                .filter(|file| **file == suffix || file.ends_with(&slash_suffix))
+                .collect();
+            match matches.as_slice() {
+                [] => continue,
+                [file] => return ResolvedPath::Existing((*file).clone()),
+                _ => return ResolvedPath::Ambiguous(matches.into_iter().cloned().collect()),
+            }
+        }
+        ResolvedPath::New(normalized)
+    }
+
+    /// Files in the repository (respecting `.gitignore`), relative and `/`-separated.
+    fn files(&self) -> &[String] {
+        self.files.get_or_init(|| {
+            let mut files: Vec<String> = WalkBuilder::new(&self.base_path_canon)
+                .hidden(false)
+                .filter_entry(|entry| entry.file_name() != ".git")
+                .build()
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+                .filter_map(|entry| {
+                    let relative = entry.path().strip_prefix(&self.base_path_canon).ok()?;
+                    let components: Vec<_> = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect();
+                    Some(components.join("/"))
+                })
+                .collect();
+            files.sort();
+            files
+        })
+    }
+}
+
+/// Checks a resolved target before anything is created, written or removed for it.
+/// Absolute paths, drive and UNC prefixes, `..` components and paths into `.git`
+/// are rejected lexically; existing ancestors inside the repository must not be
+/// symlinks, since writing through one could leave the repository. `Err` carries
+/// the reason for the report.
+pub fn validate_target(base_path_canon: &Path, relative: &str) -> Result<(), String> {
+    let bytes = relative.as_bytes();
+    if relative.trim().is_empty() {
+        return Err("empty path".to_string());
+    }
+    if relative.starts_with(['/', '\\']) || Path::new(relative).is_absolute() {
+        return Err("absolute path".to_string());
+    }
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Err("path has a drive prefix".to_string());
+    }
+
+    let components: Vec<&str> = relative
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    if components.contains(&"..") {
+        return Err("path contains `..`".to_string());
+    }
+    if components.iter().any(|c| c.eq_ignore_ascii_case(".git")) {
+        return Err("path points into .git".to_string());
+    }
+
+    let mut ancestor = base_path_canon.to_path_buf();
+    for component in &components[..components.len().saturating_sub(1)] {
+        ancestor.push(component);
+        match ancestor.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(format!(
+                    "path goes through a symlink: {}",
+                    ancestor
+                        .strip_prefix(base_path_canon)
+                        .unwrap_or(&ancestor)
+                        .display()
+                ));
+            }
+            Ok(_) => {}
+            Err(_) => break, // The rest does not exist yet
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! Detects "lazy" model output: full-file blocks that elide parts of the file with
+//! placeholders such as `// ... rest of the code unchanged ...`, or that are much
+//! shorter than the file they would replace. Unambiguous placeholders can be
+//! spliced back from the original file.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::{collections::HashSet, path::Path};
+
+/// Line patterns treated as placeholders when none are configured. A line matches
+/// when it is a bare `...` (optionally commented), a comment with `...` saying the
+/// rest of the code is unchanged or exists elsewhere, a comment saying the rest of
+/// the file is unchanged, or a comment that only says `existing code here`.
+pub const DEFAULT_PLACEHOLDER_PATTERNS: &[&str] = &[
+    r"^\s*(//+|#+|/\*+|\*|<!--|--|;+)?\s*(\.{3,}|…)\s*(\*/|-->)?\s*$",
+    r"(?i)^\s*(//+|#+|/\*+|\*|<!--|--|;+)\s*(\.{3,}|…)\s*(the\s+)?(rest|remainder|remaining|existing|unchanged|previous|other|same)\b.*\b(code|implementation|methods?|functions?|file|content|logic|unchanged|here|before|above|below)\b",
+    r"(?i)^\s*(//+|#+|/\*+|\*|<!--|--|;+)\s*(the\s+)?(rest|remainder)\s+of\s+(the\s+)?(\w+\s+)?(code|implementation|methods|functions|file|class|module|content|logic)\b.*\b(unchanged|remains?|same|omitted|as\s+before)\b",
+    r"(?i)^\s*(//+|#+|/\*+|\*|<!--|--|;+)\s*(existing|unchanged|previous|other)\s+(code|implementation|methods|functions|logic|content)(\s+(here|goes\s+here|unchanged|as\s+before|remains?\s+(the\s+same|unchanged)))?\s*(\.{3,}|…)?\s*(\*/|-->)?\s*$",
+];
+
+/// Files shorter than this are never flagged by the shrink heuristic.
+const MIN_LINES_FOR_SHRINK_CHECK: usize = 20;
+
+/// Default for [`PlaceholderPolicy::max_shrink_percent`].
+pub const DEFAULT_MAX_SHRINK_PERCENT: u32 = 50;
+
+/// How the `Injector` treats blocks that look like lazy output.
+#[derive(Debug, Clone, Default)]
+pub struct PlaceholderPolicy {
+    /// Regexes matched against each line; replaces [`DEFAULT_PLACEHOLDER_PATTERNS`].
+    pub patterns: Option<Vec<String>>,
+    /// Refuse blocks with fewer lines than this percentage of the file they replace
+    /// would lose (defaults to [`DEFAULT_MAX_SHRINK_PERCENT`]).
+    pub max_shrink_percent: Option<u32>,
+    /// Write such blocks anyway.
+    pub allow: bool,
+    /// Fill unambiguous placeholders with the elided lines of the original file.
+    pub splice: bool,
+}
+
+/// Why a block was considered lazy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LazyOutput {
+    /// The block contains placeholder lines (0-based indices into the block).
+    Placeholders(Vec<usize>),
+    /// The block has this many percent fewer lines than the original.
+    Shrunk(u32),
+}
+
+/// Compiled form of a [`PlaceholderPolicy`].
+#[derive(Debug)]
+pub struct PlaceholderDetector {
+    patterns: Vec<Regex>,
+    max_shrink_percent: u32,
+}
+
+impl PlaceholderDetector {
+    pub fn new(policy: &PlaceholderPolicy) -> Result<Self> {
+        let patterns = match &policy.patterns {
+            Some(patterns) => patterns.iter().map(String::as_str).collect(),
+            None => DEFAULT_PLACEHOLDER_PATTERNS.to_vec(),
+        };
+        let patterns = patterns
+            .into_iter()
+            .map(|p| Regex::new(p).with_context(|| format!("Invalid placeholder pattern: '{p}'")))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            patterns,
+            max_shrink_percent: policy
+                .max_shrink_percent
+                .unwrap_or(DEFAULT_MAX_SHRINK_PERCENT),
+        })
+    }
+
+    /// Checks `new`, the content for `path`, against the content it would replace.
+    /// New files (`original` is `None`) have nothing to elide and always pass. Lines
+    /// that also occur in the original are not placeholders, so files that
+    /// legitimately contain `...` are left alone, and neither is a bare `...`
+    /// statement body in Python.
+    pub fn check(&self, path: &Path, original: Option<&str>, new: &str) -> Option<LazyOutput> {
+        let original = original?;
+        let original_lines: HashSet<&str> = original.lines().map(str::trim).collect();
+        let is_python = path
+            .extension()
+            .is_some_and(|extension| extension == "py" || extension == "pyi");
+        let new_lines: Vec<&str> = new.lines().collect();
+        let placeholders: Vec<usize> = new_lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !original_lines.contains(line.trim()))
+            .filter(|(_, line)| self.patterns.iter().any(|p| p.is_match(line)))
+            .filter(|(index, _)| !(is_python && is_python_ellipsis_body(&new_lines, *index)))
+            .map(|(index, _)| index)
+            .collect();
+        if !placeholders.is_empty() {
+            return Some(LazyOutput::Placeholders(placeholders));
+        }
+
+        let original_count = original.lines().count();
+        if original_count < MIN_LINES_FOR_SHRINK_CHECK {
+            return None;
+        }
+        let new_count = new.lines().count();
+        let shrink = original_count.saturating_sub(new_count) * 100 / original_count;
+        (shrink as u32 > self.max_shrink_percent).then_some(LazyOutput::Shrunk(shrink as u32))
+    }
+}
+
+/// True when line `index` is a bare `...` that is the body of the block opened
+/// above it (`def f(self) -> int:` or a class header, optionally followed by a
+/// docstring), as in protocols and stubs.
+fn is_python_ellipsis_body(lines: &[&str], index: usize) -> bool {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let line = lines[index];
+    if line.trim() != "..." {
+        return false;
+    }
+    let previous = |end: usize| (0..end).rev().find(|&i| !lines[i].trim().is_empty());
+    let Some(mut header) = previous(index) else {
+        return false;
+    };
+    // Skip a docstring between the header and the `...`
+    let closing = lines[header].trim();
+    if let Some(quote) = ["\"\"\"", "'''"].into_iter().find(|q| closing.ends_with(q)) {
+        let single_line = closing.len() >= 6 && closing.starts_with(quote);
+        if !single_line {
+            match (0..header)
+                .rev()
+                .find(|&i| lines[i].trim_start().starts_with(quote))
+            {
+                Some(opening) => header = opening,
+                None => return false,
+            }
+        }
+        match previous(header) {
+            Some(before) => header = before,
+            None => return false,
+        }
+    }
+    lines[header].trim_end().ends_with(':') && indent(lines[header]) < indent(line)
+}
+
+/// Replaces every placeholder line of `new` with the lines of `original` it stands
+/// for. Each placeholder (with the blank lines around it) is bounded by the nearest
+/// lines before and after it that also occur in the original, or by the start or
+/// end of the block; these anchors must occur exactly once, in order. Returns
+/// `None` when any placeholder is ambiguous.
+pub fn splice(original: &str, new: &str, placeholders: &[usize]) -> Option<String> {
+    let original_lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let is_gap = |index: usize| placeholders.contains(&index) || new_lines[index].trim().is_empty();
+    // Position of a line in the original: `Ok(None)` when it is new, `Err` when it
+    // occurs more than once and cannot anchor anything
+    let locate = |line: &str| -> std::result::Result<Option<usize>, ()> {
+        let mut matches = original_lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.trim() == line.trim())
+            .map(|(index, _)| index);
+        match (matches.next(), matches.next()) {
+            (first, None) => Ok(first),
+            _ => Err(()),
+        }
+    };
+
+    let mut result = String::new();
+    let mut emitted_until = 0;
+    let mut previous_region_end = 0;
+    for &placeholder in placeholders {
+        if placeholder < emitted_until {
+            continue; // Already covered by the previous gap
+        }
+        // Widen the gap over neighbouring blank lines and placeholders
+        let mut gap_start = placeholder;
+        while gap_start > emitted_until && is_gap(gap_start - 1) {
+            gap_start -= 1;
+        }
+        let mut gap_end = placeholder + 1;
+        while gap_end < new_lines.len() && is_gap(gap_end) {
+            gap_end += 1;
+        }
+
+        // Anchor on the nearest lines that exist in the original, skipping added ones
+        let mut region_start = 0;
+        for line in new_lines[..gap_start].iter().rev() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(position) = locate(line).ok()? {
+                region_start = position + 1;
+                break;
+            }
+        }
+        let mut region_end = original_lines.len();
+        for (index, line) in new_lines.iter().enumerate().skip(gap_end) {
+            if placeholders.contains(&index) {
+                return None; // The next placeholder came first
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(position) = locate(line).ok()? {
+                region_end = position;
+                break;
+            }
+        }
+        if region_start < previous_region_end || region_end < region_start {
+            return None;
+        }
+
+        new_lines[emitted_until..gap_start]
+            .iter()
+            .for_each(|line| result.push_str(line));
+        original_lines[region_start..region_end]
+            .iter()
+            .for_each(|line| result.push_str(line));
+        emitted_until = gap_end;
+        previous_region_end = region_end;
+    }
+    new_lines[emitted_until..]
+        .iter()
+        .for_each(|line| result.push_str(line));
+    Some(result)
+}
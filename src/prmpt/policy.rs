@@ -0,0 +1,142 @@
+//! Write policy for injection: hard limits on what a response may touch beyond
+//! staying inside the repository, configured under `inject.policy` in `prmpt.yaml`.
+
+use crate::prmpt::utils::compile_globs;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Paths that are never written, deleted or renamed unless `replace_default_deny` is set.
+pub const DEFAULT_DENY: &[&str] = &[
+    ".git/**",
+    "prmpt.yaml",
+    "**/Cargo.lock",
+    "**/package-lock.json",
+    "**/npm-shrinkwrap.json",
+    "**/yarn.lock",
+    "**/pnpm-lock.yaml",
+    "**/bun.lockb",
+    "**/poetry.lock",
+    "**/Pipfile.lock",
+    "**/uv.lock",
+    "**/Gemfile.lock",
+    "**/composer.lock",
+    "**/go.sum",
+];
+
+/// `*` and `?` stay within one path component; `**` crosses directories.
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Bytes inspected when deciding whether a file is binary.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// The `inject.policy` section of the configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WritePolicy {
+    /// Globs of paths blocks may touch; when set, every other path is refused.
+    pub allow: Option<Vec<String>>,
+    /// Globs of paths blocks may never touch, in addition to [`DEFAULT_DENY`].
+    pub deny: Option<Vec<String>>,
+    /// Use only `deny`, without [`DEFAULT_DENY`] (default false).
+    pub replace_default_deny: Option<bool>,
+    /// Allow blocks to create files that do not exist yet (default true).
+    pub create_files: Option<bool>,
+    /// Allow creating missing parent directories (default true).
+    pub create_dirs: Option<bool>,
+    /// Refuse writes whose resulting file is larger than this many bytes.
+    pub max_file_size: Option<u64>,
+    /// Refuse the whole response when it contains more blocks than this.
+    pub max_blocks: Option<usize>,
+}
+
+/// Compiled form of a [`WritePolicy`].
+#[derive(Debug)]
+pub struct PolicyChecker {
+    allow: Option<Vec<glob::Pattern>>,
+    deny: Vec<glob::Pattern>,
+    create_files: bool,
+    create_dirs: bool,
+    max_file_size: Option<u64>,
+    max_blocks: Option<usize>,
+}
+
+impl PolicyChecker {
+    pub fn new(policy: &WritePolicy) -> Result<Self> {
+        Ok(Self {
+            allow: policy
+                .allow
+                .as_ref()
+                .map(|globs| compile_globs(globs))
+                .transpose()?,
+            deny: {
+                let mut deny = match policy.replace_default_deny {
+                    Some(true) => Vec::new(),
+                    _ => compile_globs(DEFAULT_DENY)?,
+                };
+                deny.extend(compile_globs(policy.deny.as_deref().unwrap_or_default())?);
+                deny
+            },
+            create_files: policy.create_files.unwrap_or(true),
+            create_dirs: policy.create_dirs.unwrap_or(true),
+            max_file_size: policy.max_file_size,
+            max_blocks: policy.max_blocks,
+        })
+    }
+
+    /// Checks the number of blocks in a response.
+    pub fn check_block_count(&self, count: usize) -> Result<(), String> {
+        match self.max_blocks {
+            Some(max) if count > max => Err(format!(
+                "response has {count} blocks, more than the policy allows ({max})"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the repository-relative `path` may be touched at all.
+    pub fn check_path(&self, path: &str) -> Result<(), String> {
+        let matches = |pattern: &&glob::Pattern| pattern.matches_with(path, MATCH_OPTIONS);
+        if let Some(pattern) = self.deny.iter().find(matches) {
+            return Err(format!("denied by policy ({pattern})"));
+        }
+        match &self.allow {
+            Some(allow) if !allow.iter().any(|p| matches(&p)) => {
+                Err("not in the policy's allow list".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that writing `path` would not create a file or directory the policy
+    /// forbids. Only reads the file system.
+    pub fn check_creation(&self, base_path_canon: &Path, path: &str) -> Result<(), String> {
+        let full_path = base_path_canon.join(path);
+        if !self.create_dirs && full_path.parent().is_some_and(|parent| !parent.is_dir()) {
+            return Err("policy forbids creating directories".to_string());
+        }
+        if !self.create_files && !full_path.exists() {
+            return Err("policy forbids creating files".to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks the content a write would leave in the file.
+    pub fn check_size(&self, content: &str) -> Result<(), String> {
+        match self.max_file_size {
+            Some(max) if content.len() as u64 > max => Err(format!(
+                "{} bytes, more than the policy's max_file_size ({max})",
+                content.len()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// True for contents that look binary: a NUL byte near the start, or invalid UTF-8.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) || std::str::from_utf8(bytes).is_err()
+}
@@ -0,0 +1,258 @@
+//! Structured result of an injection run: what happened to every parsed block and
+//! which post-inject hooks ran. Printed by the CLI as a table or as JSON.
+
+use serde::Serialize;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// What the `Injector` did with a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockAction {
+    /// A new file was written.
+    Created,
+    /// An existing file was overwritten (or merged).
+    Modified,
+    /// The file already had the block's content; nothing was written.
+    Unchanged,
+    /// The file was removed by a `DELETE` directive.
+    Deleted,
+    /// The file was moved by a `RENAME` directive.
+    Renamed,
+    /// Nothing was done; `reason` explains why.
+    Skipped,
+}
+
+impl fmt::Display for BlockAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BlockAction::Created => "created",
+            BlockAction::Modified => "modified",
+            BlockAction::Unchanged => "unchanged",
+            BlockAction::Deleted => "deleted",
+            BlockAction::Renamed => "renamed",
+            BlockAction::Skipped => "skipped",
+        };
+        f.pad(name)
+    }
+}
+
+/// Report entry for a single block (or directive) of the input.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockReport {
+    /// First line of the block in the input, 1-based (the path line when separate).
+    pub start_line: usize,
+    /// Last line of the block in the input, 1-based (the closing fence).
+    pub end_line: usize,
+    /// Target path as written in the input, if one was found.
+    pub target_path: Option<String>,
+    /// Repository-relative path the block resolved to.
+    pub resolved_path: Option<PathBuf>,
+    /// Repository-relative destination of a `RENAME`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<PathBuf>,
+    pub action: BlockAction,
+    /// Why a block was skipped, or a note about how it was applied (e.g. merged).
+    pub reason: Option<String>,
+    /// Size of the file after the operation minus its size before, in bytes.
+    pub byte_delta: i64,
+}
+
+impl BlockReport {
+    /// Creates an entry for the given input lines, initially marked as skipped.
+    pub fn new(start_line: usize, end_line: usize, target_path: Option<String>) -> Self {
+        Self {
+            start_line,
+            end_line,
+            target_path,
+            resolved_path: None,
+            destination: None,
+            action: BlockAction::Skipped,
+            reason: None,
+            byte_delta: 0,
+        }
+    }
+
+    /// Marks the entry as skipped for `reason`.
+    pub fn skip(mut self, reason: impl Into<String>) -> Self {
+        self.action = BlockAction::Skipped;
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+/// Lines of a failed command's output shown in the summary table; the JSON report
+/// keeps all of it.
+const OUTPUT_LINES_SHOWN: usize = 20;
+
+/// The result of running a post-inject hook on one file, or the verify command.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOutcome {
+    /// Repository-relative path of the file a hook ran on (`None` for verify).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// The command line as run; for hooks with `{file}` substituted.
+    pub command: String,
+    /// Exit code of the command, `None` if it could not be started or was killed.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the touched files were restored because the verify command failed.
+    pub rolled_back: bool,
+}
+
+impl CommandOutcome {
+    /// An outcome for `command` that has not run yet.
+    pub fn new(command: impl Into<String>, file: Option<PathBuf>) -> Self {
+        Self {
+            file,
+            command: command.into(),
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            rolled_back: false,
+        }
+    }
+
+    /// True when the command ran and exited with status 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Writes `<kind> ok: <command>`, or the failure status followed by the
+    /// command's output, indented and cut to [`OUTPUT_LINES_SHOWN`] lines.
+    fn write_summary(&self, f: &mut fmt::Formatter<'_>, kind: &str) -> fmt::Result {
+        if self.success() {
+            return writeln!(f, "{kind} ok: {}", self.command);
+        }
+        let status = self
+            .exit_code
+            .map_or_else(|| "failed".to_string(), |code| format!("failed ({code})"));
+        writeln!(f, "{kind} {status}: {}", self.command)?;
+        let output = format!("{}{}", self.stdout, self.stderr);
+        let lines: Vec<&str> = output.trim_end().lines().collect();
+        for line in lines.iter().take(OUTPUT_LINES_SHOWN) {
+            writeln!(f, "  {line}")?;
+        }
+        if lines.len() > OUTPUT_LINES_SHOWN {
+            writeln!(f, "  ... {} more line(s)", lines.len() - OUTPUT_LINES_SHOWN)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line of the input that strict parsing refused to guess a path from.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseWarning {
+    /// Line number in the input, 1-based.
+    pub line: usize,
+    /// The line, trimmed.
+    pub text: String,
+    pub reason: String,
+}
+
+/// Everything an injection run did, in input order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InjectionReport {
+    pub blocks: Vec<BlockReport>,
+    /// Ambiguous lines reported by strict parsing.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ParseWarning>,
+    /// Post-inject hooks that ran on written files.
+    pub hooks: Vec<CommandOutcome>,
+    /// Result of the verify command, if one was given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<CommandOutcome>,
+    /// Hash of the commit created for the touched files, if one was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+impl InjectionReport {
+    /// Number of blocks with the given action.
+    pub fn count(&self, action: BlockAction) -> usize {
+        self.blocks.iter().filter(|b| b.action == action).count()
+    }
+
+    /// True when at least one block was skipped.
+    pub fn has_skipped(&self) -> bool {
+        self.count(BlockAction::Skipped) > 0
+    }
+
+    /// Repository-relative paths whose content was written (created or modified).
+    pub fn written_paths(&self) -> Vec<&Path> {
+        self.blocks
+            .iter()
+            .filter(|b| matches!(b.action, BlockAction::Created | BlockAction::Modified))
+            .filter_map(|b| b.resolved_path.as_deref())
+            .collect()
+    }
+
+    /// Repository-relative paths that were written, deleted, or renamed (both ends).
+    pub fn touched_paths(&self) -> Vec<&Path> {
+        self.blocks
+            .iter()
+            .filter(|b| !matches!(b.action, BlockAction::Unchanged | BlockAction::Skipped))
+            .flat_map(|b| [b.resolved_path.as_deref(), b.destination.as_deref()])
+            .flatten()
+            .collect()
+    }
+}
+
+impl fmt::Display for InjectionReport {
+    /// Renders the report as a summary table.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<11} {:<9} {:>8}  Path", "Lines", "Action", "Delta")?;
+        for block in &self.blocks {
+            let lines = format!("{}-{}", block.start_line, block.end_line);
+            let path = match (&block.resolved_path, &block.target_path) {
+                (Some(resolved), _) => resolved.to_string_lossy().into_owned(),
+                (None, Some(target)) => target.clone(),
+                (None, None) => "-".to_string(),
+            };
+            let delta = format!("{:+}", block.byte_delta);
+            write!(f, "{lines:<11} {:<9} {delta:>8}  {path}", block.action)?;
+            if let Some(destination) = &block.destination {
+                write!(f, " -> {}", destination.display())?;
+            }
+            if let Some(reason) = &block.reason {
+                write!(f, " ({reason})")?;
+            }
+            writeln!(f)?;
+        }
+
+        for warning in &self.warnings {
+            writeln!(
+                f,
+                "line {}: {}: {}",
+                warning.line, warning.reason, warning.text
+            )?;
+        }
+        for hook in &self.hooks {
+            hook.write_summary(f, "hook")?;
+        }
+        if let Some(verification) = &self.verification {
+            verification.write_summary(f, "verify")?;
+            if verification.rolled_back {
+                writeln!(f, "all touched files were rolled back")?;
+            }
+        }
+        if let Some(commit) = &self.commit {
+            writeln!(f, "committed {commit}")?;
+        }
+
+        write!(
+            f,
+            "{} block(s): {} created, {} modified, {} unchanged, {} deleted, {} renamed, {} skipped",
+            self.blocks.len(),
+            self.count(BlockAction::Created),
+            self.count(BlockAction::Modified),
+            self.count(BlockAction::Unchanged),
+            self.count(BlockAction::Deleted),
+            self.count(BlockAction::Renamed),
+            self.count(BlockAction::Skipped),
+        )
+    }
+}
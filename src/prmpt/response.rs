@@ -0,0 +1,861 @@
+//! Parses model responses into the blocks prmpt injects. This is the parser the
+//! `inject` command uses; [`parse_response`] exposes it so other tools can preview
+//! or post-process a response without touching any files.
+
+use crate::prmpt::json_response::parse_json_response;
+use crate::prmpt::report::{BlockReport, ParseWarning};
+use crate::prmpt::symbols::split_symbol_target;
+use crate::prmpt::utils::fence_char;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, str::FromStr};
+
+/// Parser states for processing injection file content
+#[derive(Debug, PartialEq)]
+enum ParserState {
+    ExpectingPath,
+    InCodeBlock,
+}
+
+/// The file system operation requested by a parsed block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockOperation {
+    /// Create or overwrite the target file with the block content
+    Write,
+    /// Remove the target file, e.g. `DELETE src/old.rs`
+    Delete,
+    /// Move the target file to the contained path, e.g. `RENAME old.rs -> new.rs`
+    Rename(String),
+    /// Replace text in the existing target file, from an `<edit>` element
+    Edit(Vec<SearchReplace>),
+}
+
+/// One `<search>`/`<replace>` pair of an `<edit>` element.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchReplace {
+    /// Text that must occur exactly once in the file
+    pub search: String,
+    pub replace: String,
+}
+
+/// How the target path of a block was written in the response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathMarker {
+    /// On the opening fence: "```src/lib.rs" or "```rust src/lib.rs"
+    Fence,
+    /// A heading: ``### `src/lib.rs` ``
+    Heading,
+    /// Bold inline code: ``**`src/lib.rs`**``
+    Bold,
+    /// Inline code on its own line: `` `src/lib.rs` ``
+    Backticks,
+    /// A bare line without spaces: `src/lib.rs`
+    Bare,
+    /// A `DELETE` or `RENAME` directive
+    Directive,
+    /// An XML element: `<file path="src/lib.rs">` or `<edit path="src/lib.rs">`
+    XmlTag,
+    /// An operation in a JSON response
+    Json,
+}
+
+/// The format of a model response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// Markdown with fenced code blocks, directives and XML-tagged blocks
+    #[default]
+    Markdown,
+    /// File operations as JSON, see [`parse_json_response`]
+    Json,
+}
+
+impl FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Ok(ResponseFormat::Markdown),
+            "json" => Ok(ResponseFormat::Json),
+            other => Err(format!(
+                "unknown response format `{other}` (expected markdown or json)"
+            )),
+        }
+    }
+}
+
+/// Options for [`parse_response`], mirroring the corresponding `Injector` fields.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Code block delimiter (defaults to "```").
+    pub delimiter: Option<String>,
+    /// Only accept explicit path and directive markers, see `Injector::strict_paths`.
+    pub strict_paths: bool,
+    pub format: ResponseFormat,
+}
+
+/// A block prmpt would inject, as parsed from a model response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParsedBlock {
+    /// Target file path as written, without any symbol suffix. Paths are not yet
+    /// resolved against a repository.
+    pub target_path: String,
+    /// Function, class or impl item to replace instead of the whole file
+    pub symbol: Option<String>,
+    /// Language from the info string of the opening fence, e.g. `rust`
+    pub language: Option<String>,
+    /// Block content without the fences or tags (empty for directives and edits)
+    pub content: String,
+    pub operation: BlockOperation,
+    /// 1-based line range of the block in the response, starting at its path marker
+    pub start_line: usize,
+    pub end_line: usize,
+    pub marker: PathMarker,
+}
+
+/// Everything the parser found in a response.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedResponse {
+    pub blocks: Vec<ParsedBlock>,
+    /// Blocks dropped while parsing, e.g. fences without a file path
+    pub skipped: Vec<BlockReport>,
+    /// Lines ignored in strict mode that would otherwise have been taken as paths
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Parses a model response into the blocks the CLI would inject, in order.
+pub fn parse_response(response: &str, options: &ParseOptions) -> Vec<ParsedBlock> {
+    parse_response_with_diagnostics(response, options).blocks
+}
+
+/// Like [`parse_response`], but also returns the skipped blocks and strict-mode
+/// warnings that end up in the `InjectionReport`. A JSON response that cannot be
+/// parsed at all is reported as one skipped block.
+pub fn parse_response_with_diagnostics(response: &str, options: &ParseOptions) -> ParsedResponse {
+    if options.format == ResponseFormat::Json {
+        return parse_json_response(response).unwrap_or_else(|e| ParsedResponse {
+            skipped: vec![
+                BlockReport::new(1, response.lines().count().max(1), None).skip(format!("{e:#}"))
+            ],
+            ..Default::default()
+        });
+    }
+    let delimiter = options.delimiter.as_deref().unwrap_or("```");
+    InjectionParser::new(delimiter, options.strict_paths).parse(response)
+}
+
+/// Parser for processing injection file content
+struct InjectionParser {
+    delimiter: String,
+    strict: bool,
+    /// Length of the fence that opened the current code block
+    open_fence_len: usize,
+    state: ParserState,
+    current_target_path: Option<String>,
+    /// How the current target path was written
+    current_marker: PathMarker,
+    /// Language from the info string of the current block's opening fence
+    current_language: Option<String>,
+    /// Line on which the current target path (or fence) was found
+    current_start_line: usize,
+    current_code_block: String,
+    /// Length of an ```xml fence wrapping XML-tagged blocks, which are parsed as
+    /// if the fence were not there
+    xml_fence_len: Option<usize>,
+    blocks: Vec<ParsedBlock>,
+    /// Blocks dropped while parsing, e.g. fences without a file path
+    skipped: Vec<BlockReport>,
+    /// Lines ignored in strict mode that would otherwise have been taken as paths
+    warnings: Vec<ParseWarning>,
+}
+
+impl InjectionParser {
+    fn new(delimiter: &str, strict: bool) -> Self {
+        Self {
+            delimiter: delimiter.to_string(),
+            strict,
+            open_fence_len: 0,
+            state: ParserState::ExpectingPath,
+            current_target_path: None,
+            current_marker: PathMarker::Bare,
+            current_language: None,
+            current_start_line: 0,
+            current_code_block: String::new(),
+            xml_fence_len: None,
+            blocks: Vec::new(),
+            skipped: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn parse(mut self, content: &str) -> ParsedResponse {
+        let mut line_number = 0;
+        let mut offset = 0;
+        // Last line of an XML element parsed ahead of the line loop
+        let mut skip_until = 0;
+        // Split on '\n' only, keeping any '\r' so CRLF and mixed files round-trip exactly
+        for raw_line in content.split_inclusive('\n') {
+            line_number += 1;
+            let rest = &content[offset..];
+            offset += raw_line.len();
+            if line_number <= skip_until {
+                continue;
+            }
+            let line = raw_line.strip_suffix('\n').unwrap_or(raw_line);
+            match self.state {
+                ParserState::ExpectingPath => {
+                    if let Some(tag) = xml_tag(line) {
+                        skip_until = self.parse_xml_element(rest, tag, line_number);
+                        self.current_target_path = None;
+                    } else if self
+                        .xml_fence_len
+                        .is_some_and(|len| is_closing_fence(line, &self.delimiter, len))
+                    {
+                        self.xml_fence_len = None;
+                    } else if let Some(fence_len) = opening_fence_len(line, &self.delimiter) {
+                        // Handle optional path on the same line as the opening code fence
+                        let fence = &line.trim_start()[..fence_len];
+                        let path_on_fence = extract_path_from_fence(line, fence)
+                            .filter(|path| self.accept_path(path, line, line_number));
+                        if let Some(path_on_fence) = path_on_fence {
+                            self.current_target_path = Some(path_on_fence.to_string());
+                            self.current_marker = PathMarker::Fence;
+                            self.current_start_line = line_number;
+                        } else if self.current_target_path.is_none() {
+                            self.current_start_line = line_number;
+                        }
+                        self.current_language = fence_language(line, fence).map(str::to_string);
+                        if self.current_target_path.is_none()
+                            && self.current_language.as_deref() == Some("xml")
+                            && next_line_is_xml_tag(&rest[raw_line.len()..])
+                        {
+                            self.xml_fence_len = Some(fence_len);
+                            self.current_language = None;
+                            continue;
+                        }
+                        self.open_fence_len = fence_len;
+                        self.state = ParserState::InCodeBlock;
+                        self.current_code_block.clear();
+                    } else if let Some(directive) = parse_directive(line, line_number) {
+                        if self.strict && !is_explicit_directive(line) {
+                            self.warn_ambiguous(
+                                line_number,
+                                line,
+                                "not an explicit directive marker",
+                            );
+                        } else {
+                            self.blocks.push(directive);
+                        }
+                        self.current_target_path = None;
+                    } else if self.is_path_line(line) {
+                        let extracted_path = extract_path(line);
+                        if self.strict && !is_explicit_path_marker(line) {
+                            self.warn_ambiguous(line_number, line, "not an explicit path marker");
+                        } else if !extracted_path.trim().is_empty() {
+                            if self.accept_path(extracted_path, line, line_number) {
+                                self.current_target_path = Some(extracted_path.to_string());
+                                self.current_marker = path_line_marker(line);
+                                self.current_start_line = line_number;
+                            }
+                        } else {
+                            warn!("Detected an empty file path! Skipping...");
+                            self.skipped.push(
+                                BlockReport::new(line_number, line_number, None)
+                                    .skip("empty file path"),
+                            );
+                            self.current_target_path = None;
+                        }
+                    } else {
+                        // Check for inline file paths
+                        let trimmed = line.trim();
+                        if !trimmed.is_empty()
+                            && !trimmed.starts_with('#')
+                            && !trimmed.contains(' ')
+                        {
+                            if self.strict {
+                                self.warn_ambiguous(
+                                    line_number,
+                                    line,
+                                    "not an explicit path marker",
+                                );
+                            } else {
+                                self.current_target_path = Some(trimmed.to_string());
+                                self.current_marker = PathMarker::Bare;
+                                self.current_start_line = line_number;
+                            }
+                        }
+                    }
+                }
+                ParserState::InCodeBlock => {
+                    if is_closing_fence(line, &self.delimiter, self.open_fence_len) {
+                        // End of code block
+                        self.finalize_current_block(line_number);
+                        self.state = ParserState::ExpectingPath;
+                    } else {
+                        self.current_code_block.push_str(line);
+                        self.current_code_block.push('\n');
+                    }
+                }
+            }
+        }
+
+        // Handle case where file ends without closing delimiter
+        if self.state == ParserState::InCodeBlock {
+            self.finalize_current_block(line_number);
+        }
+
+        ParsedResponse {
+            blocks: self.blocks,
+            skipped: self.skipped,
+            warnings: self.warnings,
+        }
+    }
+
+    /// In strict mode, checks that `path` looks like a file path and records a
+    /// warning for `line` when it does not.
+    fn accept_path(&mut self, path: &str, line: &str, line_number: usize) -> bool {
+        if !self.strict || looks_like_file_path(split_symbol_target(path).0) {
+            return true;
+        }
+        self.warn_ambiguous(line_number, line, "does not look like a file path");
+        false
+    }
+
+    fn warn_ambiguous(&mut self, line_number: usize, line: &str, reason: &str) {
+        warn!("Ignoring line {line_number} ({reason}): {:?}", line.trim());
+        self.warnings.push(ParseWarning {
+            line: line_number,
+            text: line.trim().to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    fn is_path_line(&self, line: &str) -> bool {
+        let trimmed = line.trim_start();
+        (trimmed.starts_with("### `") && line.trim_end().ends_with('`'))
+            || (trimmed.starts_with("**`") && line.trim_end().ends_with("`**"))
+            || (trimmed.starts_with('`') && line.trim_end().ends_with('`') && line.len() > 3)
+    }
+
+    /// Parses the `<file>` or `<edit>` element at the start of `text`, which begins
+    /// on `start_line`, and returns the line the element ends on.
+    fn parse_xml_element(&mut self, text: &str, tag: &str, start_line: usize) -> usize {
+        let (element, consumed) = parse_xml_element(text, tag);
+        let end_line = start_line
+            + text[..consumed]
+                .trim_end_matches('\n')
+                .matches('\n')
+                .count();
+        let (path, operation, content) = match element {
+            Ok(element) => element,
+            Err((path, reason)) => {
+                warn!("Skipping <{tag}> element on line {start_line}: {reason}");
+                self.skipped
+                    .push(BlockReport::new(start_line, end_line, path).skip(reason));
+                return end_line;
+            }
+        };
+        let line = text.lines().next().unwrap_or_default();
+        if !self.accept_path(&path, line, start_line) {
+            return end_line;
+        }
+        if operation == BlockOperation::Write && content.trim().is_empty() {
+            warn!("Empty <file> element for path: {path:?}");
+            self.skipped
+                .push(BlockReport::new(start_line, end_line, Some(path)).skip("empty code block"));
+            return end_line;
+        }
+        let (target_path, symbol) = match operation {
+            BlockOperation::Write => split_symbol_target(&path),
+            _ => (path.as_str(), None),
+        };
+        self.blocks.push(ParsedBlock {
+            target_path: target_path.to_string(),
+            symbol: symbol.map(str::to_string),
+            language: None,
+            content,
+            operation,
+            start_line,
+            end_line,
+            marker: PathMarker::XmlTag,
+        });
+        end_line
+    }
+
+    fn finalize_current_block(&mut self, end_line: usize) {
+        let report = BlockReport::new(
+            self.current_start_line,
+            end_line,
+            self.current_target_path.clone(),
+        );
+        let language_mismatch = match (&self.current_target_path, &self.current_language) {
+            (Some(path), Some(language)) if self.strict => {
+                !language_matches(language, split_symbol_target(path).0)
+            }
+            _ => false,
+        };
+        if language_mismatch {
+            let language = self.current_language.as_deref().unwrap_or_default();
+            warn!(
+                "Fence language {language:?} does not match {:?}",
+                self.current_target_path
+            );
+            self.skipped.push(report.skip(format!(
+                "fence language `{language}` does not match the file extension"
+            )));
+        } else if let Some(ref target_path) = self.current_target_path {
+            if !self.current_code_block.trim().is_empty() {
+                // Drop only the line break that precedes the closing fence
+                let content = self.current_code_block.strip_suffix('\n');
+                let content = content.unwrap_or(&self.current_code_block);
+                let content = content.strip_suffix('\r').unwrap_or(content);
+                let (path, symbol) = split_symbol_target(target_path);
+                self.blocks.push(ParsedBlock {
+                    target_path: path.to_string(),
+                    symbol: symbol.map(str::to_string),
+                    language: self.current_language.clone(),
+                    content: content.to_string(),
+                    operation: BlockOperation::Write,
+                    start_line: self.current_start_line,
+                    end_line,
+                    marker: self.current_marker,
+                });
+            } else {
+                warn!("Empty code block detected for path: {target_path:?}");
+                self.skipped.push(report.skip("empty code block"));
+            }
+        } else {
+            warn!("Code block closed without a file path being set!");
+            self.skipped
+                .push(report.skip("code block closed without a file path"));
+        }
+        self.current_target_path = None;
+        self.current_language = None;
+        self.current_code_block.clear();
+    }
+}
+
+/// Parses a file operation directive such as `DELETE src/old.rs` or
+/// `RENAME src/old.rs -> src/new.rs`. Paths may be wrapped in backticks and the
+/// directive may be written as a Markdown heading (`### DELETE ...`).
+fn parse_directive(line: &str, line_number: usize) -> Option<ParsedBlock> {
+    let trimmed = line.trim().trim_start_matches('#').trim();
+    let unquote = |path: &str| path.trim().trim_matches('`').trim().to_string();
+
+    if let Some(rest) = trimmed.strip_prefix("DELETE ") {
+        let target_path = unquote(rest);
+        if target_path.is_empty() {
+            return None;
+        }
+        Some(ParsedBlock {
+            target_path,
+            symbol: None,
+            language: None,
+            content: String::new(),
+            operation: BlockOperation::Delete,
+            start_line: line_number,
+            end_line: line_number,
+            marker: PathMarker::Directive,
+        })
+    } else if let Some(rest) = trimmed.strip_prefix("RENAME ") {
+        let (from, to) = rest.split_once("->")?;
+        let (from, to) = (unquote(from), unquote(to));
+        if from.is_empty() || to.is_empty() {
+            return None;
+        }
+        Some(ParsedBlock {
+            target_path: from,
+            symbol: None,
+            language: None,
+            content: String::new(),
+            operation: BlockOperation::Rename(to),
+            start_line: line_number,
+            end_line: line_number,
+            marker: PathMarker::Directive,
+        })
+    } else {
+        None
+    }
+}
+
+/// Helper function for extracting the path from a line
+/// that looks like `### `path/to/file` or **`path/to/file`**, etc.
+fn extract_path(input: &str) -> &str {
+    // Trim leading/trailing whitespace which might affect path extraction
+    let trimmed_input = input.trim();
+    if trimmed_input.starts_with("### `") && trimmed_input.ends_with('`') {
+        &trimmed_input[5..trimmed_input.len() - 1]
+    } else if trimmed_input.starts_with("**`") && trimmed_input.ends_with("`**") {
+        &trimmed_input[3..trimmed_input.len() - 3]
+    } else if trimmed_input.starts_with('`') && trimmed_input.ends_with('`') {
+        // Generic backtick case
+        &trimmed_input[1..trimmed_input.len() - 1]
+    } else {
+        trimmed_input // Fallback if no known pattern matches, assume the line itself is the path
+    }
+}
+
+/// Parse result of an XML element: the path, operation and content, or the path
+/// (when known) and the reason the element is skipped.
+type XmlElement = Result<(String, BlockOperation, String), (Option<String>, String)>;
+
+/// The tag name when `line` opens a `<file>` or `<edit>` element.
+fn xml_tag(line: &str) -> Option<&'static str> {
+    let trimmed = line.trim_start();
+    ["file", "edit"].into_iter().find(|tag| {
+        trimmed
+            .strip_prefix('<')
+            .and_then(|rest| rest.strip_prefix(tag))
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_whitespace() || c == '>'))
+    })
+}
+
+/// True when the first non-blank line of `text` opens an XML-tagged block.
+fn next_line_is_xml_tag(text: &str) -> bool {
+    text.lines()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| xml_tag(line).is_some())
+}
+
+/// Parses the element opened at the start of `text` and returns it with the number
+/// of bytes it spans. Content is taken literally up to the closing tag, apart from
+/// CDATA sections and entities, so unescaped code is accepted too.
+fn parse_xml_element(text: &str, tag: &str) -> (XmlElement, usize) {
+    let start = text.len() - text.trim_start().len();
+    let Some(open_end) = find_tag_end(text, start) else {
+        return (Err((None, format!("unterminated <{tag}> tag"))), text.len());
+    };
+    let open_tag = &text[start..open_end];
+    let path = xml_attribute(open_tag, "path").map(|p| decode_xml(&p).trim().to_string());
+    let closing = format!("</{tag}>");
+    let Some(body_end) = find_outside_cdata(text, open_end + 1, &closing) else {
+        return (Err((path, format!("missing {closing}"))), text.len());
+    };
+    let consumed = text[body_end..]
+        .find('\n')
+        .map_or(text.len(), |i| body_end + i + 1);
+    let Some(path) = path.filter(|p| !p.is_empty()) else {
+        return (
+            Err((None, format!("<{tag}> without a path attribute"))),
+            consumed,
+        );
+    };
+    let body = &text[open_end + 1..body_end];
+
+    let element = if tag == "file" {
+        Ok((path, BlockOperation::Write, xml_text(body)))
+    } else {
+        parse_edits(body)
+            .map(|edits| (path.clone(), BlockOperation::Edit(edits), String::new()))
+            .map_err(|reason| (Some(path), reason))
+    };
+    (element, consumed)
+}
+
+/// Reads the `<search>`/`<replace>` pairs of an `<edit>` body.
+fn parse_edits(body: &str) -> Result<Vec<SearchReplace>, String> {
+    let mut edits = Vec::new();
+    let mut position = 0;
+    while let Some(search_start) = find_outside_cdata(body, position, "<search>") {
+        let (search, after_search) = xml_child(body, search_start, "search")?;
+        let replace_start = find_outside_cdata(body, after_search, "<replace>")
+            .ok_or_else(|| "<search> without a <replace>".to_string())?;
+        let (replace, after_replace) = xml_child(body, replace_start, "replace")?;
+        if search.is_empty() {
+            return Err("empty <search>".to_string());
+        }
+        edits.push(SearchReplace { search, replace });
+        position = after_replace;
+    }
+    if edits.is_empty() {
+        return Err("<edit> without <search>/<replace> pairs".to_string());
+    }
+    Ok(edits)
+}
+
+/// Text of the `<name>` child starting at `start`, and the offset after it.
+fn xml_child(body: &str, start: usize, name: &str) -> Result<(String, usize), String> {
+    let content_start = start + name.len() + 2;
+    let closing = format!("</{name}>");
+    let end = find_outside_cdata(body, content_start, &closing)
+        .ok_or_else(|| format!("missing {closing}"))?;
+    Ok((xml_text(&body[content_start..end]), end + closing.len()))
+}
+
+/// Decodes element text and drops the line break after the opening tag and the
+/// one (with any indentation) before the closing tag.
+fn xml_text(raw: &str) -> String {
+    let text = decode_xml(raw);
+    let text = text
+        .strip_prefix("\r\n")
+        .or_else(|| text.strip_prefix('\n'))
+        .unwrap_or(&text);
+    let text = text.trim_end_matches([' ', '\t']);
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    text.strip_suffix('\r').unwrap_or(text).to_string()
+}
+
+/// Offset of the `>` closing the tag that opens at `start`, skipping quoted
+/// attribute values.
+fn find_tag_end(text: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in text[start..].char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '>') => return Some(start + index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Finds `needle` at or after `from`, ignoring occurrences inside CDATA sections.
+fn find_outside_cdata(text: &str, from: usize, needle: &str) -> Option<usize> {
+    let mut position = from;
+    loop {
+        let found = position + text[position..].find(needle)?;
+        match text[position..found].find("<![CDATA[") {
+            Some(cdata) => {
+                let cdata_start = position + cdata;
+                position = cdata_start + text[cdata_start..].find("]]>")? + 3;
+            }
+            None => return Some(found),
+        }
+    }
+}
+
+/// Value of attribute `name` in an opening tag, quoted with `"` or `'`.
+fn xml_attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(index) = rest.find(name) {
+        let preceded_by_space = rest[..index].ends_with(char::is_whitespace);
+        let after = rest[index + name.len()..].trim_start();
+        if let (true, Some(value)) = (preceded_by_space, after.strip_prefix('=')) {
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let value = &value[1..];
+            return value.find(quote).map(|end| value[..end].to_string());
+        }
+        rest = &rest[index + name.len()..];
+    }
+    None
+}
+
+/// Replaces the predefined and numeric entities outside CDATA sections and unwraps
+/// the sections. Unknown entities and stray `&` are kept as written.
+fn decode_xml(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            result.push_str(&cdata[..end]);
+            rest = cdata.get(end + 3..).unwrap_or("");
+        } else if rest.starts_with('&') {
+            let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+                let character = match &rest[1..end] {
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "amp" => Some('&'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    entity => match entity.strip_prefix('#') {
+                        Some(hex) if hex.starts_with(['x', 'X']) => {
+                            u32::from_str_radix(&hex[1..], 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                        }
+                        Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                        None => None,
+                    },
+                };
+                character.map(|c| (c, end))
+            });
+            match decoded {
+                Some((c, end)) => {
+                    result.push(c);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    result.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        } else {
+            let next = rest[1..].find(['&', '<']).map_or(rest.len(), |i| i + 1);
+            result.push_str(&rest[..next]);
+            rest = &rest[next..];
+        }
+    }
+    result
+}
+
+/// The marker style of a line accepted by `InjectionParser::is_path_line`.
+fn path_line_marker(line: &str) -> PathMarker {
+    let trimmed = line.trim();
+    if trimmed.starts_with("### `") {
+        PathMarker::Heading
+    } else if trimmed.starts_with("**`") {
+        PathMarker::Bold
+    } else {
+        PathMarker::Backticks
+    }
+}
+
+/// True for the path markers accepted in strict mode: ``### `path` `` and
+/// ``**`path`**`` (fence-line paths are handled separately).
+fn is_explicit_path_marker(line: &str) -> bool {
+    let trimmed = line.trim();
+    (trimmed.starts_with("### `") && trimmed.ends_with('`'))
+        || (trimmed.starts_with("**`") && trimmed.ends_with("`**"))
+}
+
+/// True for the directive forms accepted in strict mode: a heading with every path
+/// in backticks, ``### DELETE `path` `` and ``### RENAME `from` -> `to` ``.
+fn is_explicit_directive(line: &str) -> bool {
+    let Some(rest) = line.trim().strip_prefix("### ") else {
+        return false;
+    };
+    let quoted = |path: &str| {
+        let path = path.trim();
+        path.len() > 2 && path.starts_with('`') && path.ends_with('`')
+    };
+    if let Some(path) = rest.strip_prefix("DELETE ") {
+        quoted(path)
+    } else if let Some(paths) = rest.strip_prefix("RENAME ") {
+        paths
+            .split_once("->")
+            .is_some_and(|(from, to)| quoted(from) && quoted(to))
+    } else {
+        false
+    }
+}
+
+/// File names without an extension that are still clearly files.
+const EXTENSIONLESS_FILE_NAMES: &[&str] = &[
+    "Makefile",
+    "Dockerfile",
+    "Containerfile",
+    "Justfile",
+    "Procfile",
+    "Gemfile",
+    "Rakefile",
+    "Vagrantfile",
+    "LICENSE",
+    "README",
+    "CODEOWNERS",
+];
+
+/// Rejects prose such as `Done.` or `Here:`: a file path has no whitespace, does not
+/// end in punctuation, and its file name has an extension, is a dotfile, or is a
+/// well-known extensionless name.
+fn looks_like_file_path(path: &str) -> bool {
+    let path = path.trim();
+    if path.is_empty()
+        || path.chars().any(|c| c.is_whitespace() || c.is_control())
+        || path.ends_with(['.', ':', ',', ';', '!', '?', '/', '\\'])
+    {
+        return false;
+    }
+    let Some(file_name) = Path::new(path).file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    Path::new(file_name).extension().is_some()
+        || (file_name.starts_with('.') && file_name.len() > 1)
+        || EXTENSIONLESS_FILE_NAMES.contains(&file_name)
+}
+
+/// Language named by the info string of an opening fence, e.g. `rust` in
+/// "```rust src/lib.rs" or "```rust,ignore". `None` when the only token is a path.
+fn fence_language<'a>(line: &'a str, delimiter: &str) -> Option<&'a str> {
+    let info = line.trim_start().strip_prefix(delimiter)?.trim();
+    let mut tokens = info.split_whitespace();
+    let first = tokens.next()?;
+    if tokens.next().is_none() && extract_path_from_fence(line, delimiter).is_some() {
+        return None;
+    }
+    first.split(',').next().filter(|l| !l.is_empty())
+}
+
+/// File extensions used by common fence languages.
+fn language_extensions(language: &str) -> Option<&'static [&'static str]> {
+    Some(match language.to_ascii_lowercase().as_str() {
+        "python" | "py" | "python3" => &["py", "pyi", "pyw", "ipynb"],
+        "rust" | "rs" => &["rs"],
+        "javascript" | "js" | "jsx" => &["js", "mjs", "cjs", "jsx"],
+        "typescript" | "ts" | "tsx" => &["ts", "mts", "cts", "tsx"],
+        "json" => &["json", "jsonc", "ipynb"],
+        "yaml" | "yml" => &["yaml", "yml"],
+        "toml" => &["toml"],
+        "markdown" | "md" => &["md", "markdown"],
+        "html" => &["html", "htm"],
+        "css" => &["css"],
+        "go" | "golang" => &["go"],
+        "java" => &["java"],
+        "c" => &["c", "h"],
+        "cpp" | "c++" => &["cpp", "cc", "cxx", "hpp", "hh", "h"],
+        "ruby" | "rb" => &["rb"],
+        "bash" | "sh" | "shell" | "zsh" => &["sh", "bash", "zsh"],
+        _ => return None,
+    })
+}
+
+/// Checks a fence language against a path's extension. Unknown languages and
+/// paths without an extension always match.
+fn language_matches(language: &str, path: &str) -> bool {
+    let Some(extensions) = language_extensions(language) else {
+        return true;
+    };
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(extension) => extensions.contains(&extension.to_ascii_lowercase().as_str()),
+        None => true,
+    }
+}
+
+/// Returns the byte length of the opening fence if `line` starts a code block.
+/// For backtick/tilde delimiters any run of at least the delimiter's length counts,
+/// CommonMark style; other delimiters must match literally.
+fn opening_fence_len(line: &str, delimiter: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    match fence_char(delimiter) {
+        Some(c) => {
+            let run = trimmed.chars().take_while(|&ch| ch == c).count();
+            (run >= delimiter.len()).then_some(run)
+        }
+        None => trimmed.starts_with(delimiter).then_some(delimiter.len()),
+    }
+}
+
+/// Checks whether `line` closes a code block opened by a fence of `open_fence_len`
+/// bytes. A closing fence carries no info string and is at least as long as the
+/// opening one, so shorter fences inside the block are kept as content.
+fn is_closing_fence(line: &str, delimiter: &str, open_fence_len: usize) -> bool {
+    let trimmed = line.trim();
+    match fence_char(delimiter) {
+        Some(c) => trimmed.len() >= open_fence_len && trimmed.chars().all(|ch| ch == c),
+        None => trimmed == delimiter,
+    }
+}
+
+/// Attempt to extract a file path from a line that begins with the code block
+/// delimiter. This supports prmpt's own output format where the file path
+/// directly follows the opening fence, e.g. "```src/lib.rs" or
+/// "```rust src/lib.rs".
+fn extract_path_from_fence<'a>(line: &'a str, delimiter: &str) -> Option<&'a str> {
+    let remainder = line.trim_start().strip_prefix(delimiter)?.trim();
+    if remainder.is_empty() {
+        return None;
+    }
+    // If multiple tokens exist after the delimiter, assume the last one is the path
+    let tokens: Vec<&str> = remainder.split_whitespace().collect();
+    if tokens.len() == 1 {
+        let t = tokens[0];
+        if t.contains('/') || t.contains('.') {
+            return Some(t);
+        }
+        return None;
+    }
+    tokens.last().copied()
+}
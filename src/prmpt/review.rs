@@ -0,0 +1,163 @@
+//! Interactive review of parsed blocks before they are applied: shows a diff against
+//! the current file and asks whether to accept, reject or edit each change.
+//! Answers are read line by line from any reader (stdin in the CLI), so the review
+//! can be scripted, e.g. `printf 'y\nn\n' | prmpt inject --interactive`.
+
+use anyhow::{bail, Context, Result};
+use diffy::DiffOptions;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use std::{
+    env, fs,
+    io::{BufRead, Write},
+    path::Path,
+    process::Command,
+};
+
+/// The reviewer's answer for one block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReviewDecision {
+    /// Apply the block as proposed.
+    Accept,
+    /// Apply the block with content edited in the editor.
+    Edited(String),
+    /// Leave the target untouched.
+    Reject,
+    /// Leave this and every remaining block untouched.
+    Quit,
+}
+
+/// A single answer read from the input.
+enum Answer {
+    Yes,
+    No,
+    Edit,
+    Quit,
+}
+
+/// Asks about each block on `output` and reads the answers from `input`.
+pub struct Reviewer<'a> {
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    /// Editor command used for `e`; defaults to `$VISUAL`, then `$EDITOR`, then `vi`.
+    pub editor: Option<String>,
+    quit: bool,
+}
+
+impl<'a> Reviewer<'a> {
+    pub fn new(input: &'a mut dyn BufRead, output: &'a mut dyn Write) -> Self {
+        Self {
+            input,
+            output,
+            editor: None,
+            quit: false,
+        }
+    }
+
+    /// True once the reviewer answered `q` (or the input ran out).
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Shows the diff between `old` (`None` for a new file) and `new` for `path`
+    /// and asks for a decision. Edited content is returned as `Edited`.
+    pub fn review_write(
+        &mut self,
+        path: &str,
+        old: Option<&str>,
+        new: &str,
+    ) -> Result<ReviewDecision> {
+        let original = if old.is_some() { path } else { "/dev/null" };
+        let patch = DiffOptions::new()
+            .set_original_filename(original.to_string())
+            .set_modified_filename(path.to_string())
+            .create_patch(old.unwrap_or(""), new)
+            .to_string();
+        writeln!(self.output, "{patch}")?;
+        Ok(
+            match self.ask("Apply this change? [y]es, [n]o, [e]dit, [q]uit: ", true)? {
+                Answer::Yes => ReviewDecision::Accept,
+                Answer::Edit => ReviewDecision::Edited(self.edit(path, new)?),
+                Answer::No => ReviewDecision::Reject,
+                Answer::Quit => ReviewDecision::Quit,
+            },
+        )
+    }
+
+    /// Describes a non-content change (delete, rename) and asks for a decision.
+    /// Editing is not offered.
+    pub fn review_operation(&mut self, description: &str) -> Result<ReviewDecision> {
+        writeln!(self.output, "{description}")?;
+        Ok(
+            match self.ask("Apply this change? [y]es, [n]o, [q]uit: ", false)? {
+                Answer::Yes | Answer::Edit => ReviewDecision::Accept,
+                Answer::No => ReviewDecision::Reject,
+                Answer::Quit => ReviewDecision::Quit,
+            },
+        )
+    }
+
+    /// Prompts until a recognised answer is read. End of input counts as quit.
+    fn ask(&mut self, prompt: &str, allow_edit: bool) -> Result<Answer> {
+        loop {
+            write!(self.output, "{prompt}")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                self.quit = true;
+                return Ok(Answer::Quit);
+            }
+            match line.trim().to_ascii_lowercase().as_str() {
+                "y" | "yes" => return Ok(Answer::Yes),
+                "n" | "no" => return Ok(Answer::No),
+                "e" | "edit" if allow_edit => return Ok(Answer::Edit),
+                "q" | "quit" => {
+                    self.quit = true;
+                    return Ok(Answer::Quit);
+                }
+                other => writeln!(self.output, "Unknown answer {other:?}.")?,
+            }
+        }
+    }
+
+    /// Opens `content` in the editor and returns the saved result. The temporary
+    /// file keeps the target's extension so editors pick the right syntax.
+    fn edit(&mut self, path: &str, content: &str) -> Result<String> {
+        let editor = self
+            .editor
+            .clone()
+            .or_else(|| env::var("VISUAL").ok())
+            .or_else(|| env::var("EDITOR").ok())
+            .unwrap_or_else(|| "vi".to_string());
+        let suffix: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect();
+        let file_name = match Path::new(path).extension() {
+            Some(extension) => format!("prmpt-review-{suffix}.{}", extension.to_string_lossy()),
+            None => format!("prmpt-review-{suffix}"),
+        };
+        let temp_path = env::temp_dir().join(file_name);
+        fs::write(&temp_path, content)
+            .with_context(|| format!("Failed to write review file: {temp_path:?}"))?;
+
+        let mut args = editor.split_whitespace();
+        let Some(program) = args.next() else {
+            bail!("Empty editor command");
+        };
+        let status = Command::new(program)
+            .args(args)
+            .arg(&temp_path)
+            .status()
+            .with_context(|| format!("Failed to start editor `{editor}`"));
+        let edited = fs::read_to_string(&temp_path);
+        let _ = fs::remove_file(&temp_path);
+        let status = status?;
+        if !status.success() {
+            bail!("Editor `{editor}` exited with {status}");
+        }
+        edited.with_context(|| format!("Failed to read review file: {temp_path:?}"))
+    }
+}
@@ -16,10 +16,13 @@ use ignore::WalkBuilder; // Added
 use std::fs as std_fs; // Used for fs::canonicalize and fs::read_to_string // Added this import
 
 use super::config::Config;
-use super::parse_python::{extract_python_signatures, maybe_read_notebook};
+use super::manifest::Manifest;
+use super::parse_python::{
+    extract_python_signatures_with_comments, extract_python_stub, maybe_read_notebook, CommentKind,
+};
 // Removed get_default_ignore_patterns, get_gitignore_patterns, should_ignore from utils import
 // process_directory_structure is still used.
-use super::utils::process_directory_structure;
+use super::utils::{fence_for_content, process_directory_structure};
 // use glob::Pattern; // Removed as main ignore logic uses `ignore` crate now. Still used by process_directory_structure internally.
 use crate::prmpt::traits::GenerateOperation; // Import the trait
 use anyhow::{Context, Error}; // For the Result type & context
@@ -32,6 +35,12 @@ impl GenerateOperation for Generator {
     /// Runs the generation process based on the provided configuration.
     /// This method encapsulates the original `run` function's logic.
     fn run(&self, config: &Config) -> Result<(String, Vec<String>), Error> {
+        let (output, errors, _) = self.run_with_manifest(config)?;
+        Ok((output, errors))
+    }
+
+    /// Runs the generation process, recording a hash of every emitted file.
+    fn run_with_manifest(&self, config: &Config) -> Result<(String, Vec<String>, Manifest), Error> {
         let path_str = config.path.as_deref().unwrap_or(".");
         let repo_path = Path::new(path_str);
 
@@ -56,6 +65,18 @@ impl GenerateOperation for Generator {
                 Vec::new()
             };
         ignore_patterns_for_structure.push(glob::Pattern::new(output_file_name).unwrap());
+        let manifest_path = Manifest::path_for_output(output_file_name);
+        let manifest_file_name = manifest_path.file_name().unwrap_or_default();
+        ignore_patterns_for_structure.push(
+            glob::Pattern::new(&glob::Pattern::escape(
+                &manifest_file_name.to_string_lossy(),
+            ))
+            .unwrap(),
+        );
+        if let Some(stubs_dir) = stubs_dir_in_repo(config, &canonical_repo_path) {
+            ignore_patterns_for_structure
+                .push(glob::Pattern::new(&glob::Pattern::escape(&stubs_dir)).unwrap());
+        }
         ignore_patterns_for_structure.push(glob::Pattern::new("*.out").unwrap());
         ignore_patterns_for_structure.push(glob::Pattern::new(".git").unwrap());
         ignore_patterns_for_structure.push(glob::Pattern::new("prmpt.yaml").unwrap());
@@ -72,6 +93,7 @@ impl GenerateOperation for Generator {
 
         let output_arc = Arc::new(Mutex::new(String::new()));
         let error_count_arc = Arc::new(Mutex::new(HashMap::new()));
+        let manifest_arc = Arc::new(Mutex::new(Manifest::default()));
 
         if let Some(prompts) = &config.prompts {
             let mut output_guard = output_arc.lock().unwrap();
@@ -126,6 +148,7 @@ impl GenerateOperation for Generator {
             &canonical_repo_path,
             delimiter,
             &error_count_arc,
+            &manifest_arc,
             config,
             output_file_name,
         );
@@ -140,7 +163,8 @@ impl GenerateOperation for Generator {
             }
         }
         let final_output_string = output_arc.lock().unwrap().clone();
-        Ok((final_output_string, errors))
+        let manifest = manifest_arc.lock().unwrap().clone();
+        Ok((final_output_string, errors, manifest))
     }
 }
 
@@ -148,16 +172,25 @@ impl GenerateOperation for Generator {
 
 /// Utility function to run the generation and write the output to a file.
 /// This function now uses the GenerateOperation trait.
+/// When `config.manifest` is set, a manifest of file hashes is written next to the output.
 pub fn run_and_write(generator: &impl GenerateOperation, config: &Config) -> Result<(), Error> {
     let output_file_name = config.output.as_deref().unwrap_or("prmpt.out").to_string();
 
-    match generator.run(config) {
-        Ok((output_final, errors)) => {
+    match generator.run_with_manifest(config) {
+        Ok((output_final, errors, manifest)) => {
             if let Err(e) = std_fs::write(&output_file_name, &*output_final) {
                 return Err(
                     Error::new(e).context(format!("Unable to write to file {output_file_name}"))
                 );
             }
+            if config.manifest.unwrap_or(false) {
+                manifest.write(&Manifest::path_for_output(&output_file_name))?;
+            }
+            if let Some(stubs_dir) = &config.stubs_dir {
+                for failure in write_python_stubs(config, &manifest, Path::new(stubs_dir)) {
+                    warn!("{failure}");
+                }
+            }
             if !errors.is_empty() {
                 // Log non-critical errors from the run process
                 for error_msg in errors {
@@ -174,6 +207,57 @@ pub fn run_and_write(generator: &impl GenerateOperation, config: &Config) -> Res
     }
 }
 
+/// Writes a `.pyi` stub of every Python file emitted in docs-only mode to
+/// `stubs_dir`, mirroring the repository layout. Stubs are built from the contents
+/// recorded in `manifest`, i.e. what the prompt was generated from. Returns a
+/// message for every stub that could not be written.
+fn write_python_stubs(config: &Config, manifest: &Manifest, stubs_dir: &Path) -> Vec<String> {
+    let python_docs = config.docs_comments_only.unwrap_or(false)
+        && config
+            .language
+            .as_deref()
+            .is_some_and(|language| language.eq_ignore_ascii_case("python"));
+    if !python_docs {
+        return Vec::new();
+    }
+    let comment_kinds = config
+        .docs_comment_kinds
+        .as_deref()
+        .unwrap_or(CommentKind::ALL);
+    let mut failures = Vec::new();
+    for (relative_path, entry) in &manifest.files {
+        let relative_path = Path::new(relative_path);
+        if relative_path
+            .extension()
+            .is_none_or(|extension| extension != "py")
+        {
+            continue;
+        }
+        let Some(contents) = &entry.snapshot else {
+            continue;
+        };
+        let stub_path = stubs_dir.join(relative_path.with_extension("pyi"));
+        let written = stub_path
+            .parent()
+            .map_or(Ok(()), std_fs::create_dir_all)
+            .and_then(|()| std_fs::write(&stub_path, extract_python_stub(contents, comment_kinds)));
+        if let Err(e) = written {
+            failures.push(format!("Unable to write stub {}: {e}", stub_path.display()));
+        }
+    }
+    failures
+}
+
+/// The repository-relative path of `config.stubs_dir` when it lies inside the
+/// repository. Like `output`, the directory is relative to the working directory.
+fn stubs_dir_in_repo(config: &Config, canonical_repo_path: &Path) -> Option<String> {
+    let stubs_dir = std::path::absolute(config.stubs_dir.as_deref()?).ok()?;
+    let stubs_dir = std_fs::canonicalize(&stubs_dir).unwrap_or(stubs_dir);
+    let relative = stubs_dir.strip_prefix(canonical_repo_path).ok()?;
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    (!relative.is_empty()).then_some(relative)
+}
+
 /// Helper function to provide language-specific default ignore patterns for the `ignore` crate.
 /// These patterns should be in .gitignore format.
 fn get_default_ignore_patterns_for_ignore(language: &str) -> Vec<String> {
@@ -240,6 +324,7 @@ fn get_default_ignore_patterns_for_ignore(language: &str) -> Vec<String> {
 }
 
 /// Iterates over files in a directory and processes each one, collecting the results into `output`.
+#[allow(clippy::too_many_arguments)]
 fn process_directory_files(
     dir: &Path,
     output: &Arc<Mutex<String>>,
@@ -247,6 +332,7 @@ fn process_directory_files(
     // ignore_patterns: &[Pattern], // Removed
     delimiter: &str,
     error_count: &Arc<Mutex<HashMap<String, usize>>>,
+    manifest: &Arc<Mutex<Manifest>>,
     config: &Config,
     output_file_name: &str, // Added to ignore the output file specifically
 ) {
@@ -263,6 +349,21 @@ fn process_directory_files(
     if let Err(e) = override_builder.add(&format!("!{output_file_name}")) {
         warn!("Failed to add output file ignore pattern '{output_file_name}': {e}");
     }
+    // Match the manifest by file name, as the output path may be absolute
+    let manifest_path = Manifest::path_for_output(output_file_name);
+    let manifest_file_name = manifest_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    if let Err(e) = override_builder.add(&format!("!{manifest_file_name}")) {
+        warn!("Failed to add manifest file ignore pattern '{manifest_file_name}': {e}");
+    }
+    // Stubs written by earlier runs are not part of the repository's code
+    if let Some(stubs_dir) = stubs_dir_in_repo(config, dir) {
+        if let Err(e) = override_builder.add(&format!("!/{stubs_dir}/")) {
+            warn!("Failed to add stubs directory ignore pattern '{stubs_dir}': {e}");
+        }
+    }
     if let Err(e) = override_builder.add("!*.out") {
         warn!("Failed to add generic .out ignore pattern: {e}");
     }
@@ -360,6 +461,11 @@ fn process_directory_files(
             } else if !local_output.is_empty() {
                 let mut output_guard = output.lock().unwrap();
                 output_guard.push_str(&local_output);
+                // Record what the model is about to see, for stale-edit detection
+                if let Ok(bytes) = std_fs::read(path) {
+                    let relative = path.strip_prefix(&canonical_base_path).unwrap_or(path);
+                    manifest.lock().unwrap().record(relative, &bytes);
+                }
             }
         }
     }
@@ -418,12 +524,19 @@ fn process_file(
 
             // Process Python file to extract signatures and docstrings
             let contents = std_fs::read_to_string(file)?; // Use std_fs
-            let signatures = extract_python_signatures(&contents);
+            let comment_kinds = config
+                .docs_comment_kinds
+                .as_deref()
+                .unwrap_or(CommentKind::ALL);
+            let stubs = config.docs_stubs.unwrap_or(false);
+            let signatures = if stubs {
+                extract_python_stub(&contents, comment_kinds)
+            } else {
+                extract_python_signatures_with_comments(&contents, comment_kinds)
+            };
 
             if !signatures.trim().is_empty() {
-                output.push_str(&format!("{delimiter}{relative_path_str}\n"));
-                output.push_str(&signatures);
-                output.push_str(&format!("\n{delimiter}\n\n"));
+                push_fenced_block(output, delimiter, relative_path_str, &signatures);
             }
             return Ok(());
         }
@@ -433,7 +546,7 @@ fn process_file(
     if let Some(ext) = file.extension().and_then(std::ffi::OsStr::to_str) {
         if ext == "ipynb" {
             if let Some(notebook_json) = maybe_read_notebook(&file.to_string_lossy()) {
-                output.push_str(&format!("{delimiter}{relative_path_str}\n"));
+                let mut body = String::new();
 
                 // Attempt to read cells from the notebook
                 if let Some(cells) = notebook_json.get("cells").and_then(|c| c.as_array()) {
@@ -444,20 +557,20 @@ fn process_file(
                                 "code" => {
                                     if let Some(src) = cell.get("source").and_then(|s| s.as_array())
                                     {
-                                        output.push_str(&format!("// Cell #{i} (code)\n"));
+                                        body.push_str(&format!("// Cell #{i} (code)\n"));
                                         for line_val in src {
                                             if let Some(line_str) = line_val.as_str() {
-                                                output.push_str(line_str);
+                                                body.push_str(line_str);
                                             }
                                         }
-                                        output.push('\n');
+                                        body.push('\n');
                                     }
                                     // If display_outputs is enabled, print outputs
                                     if config.display_outputs.unwrap_or(false) {
                                         if let Some(outputs) =
                                             cell.get("outputs").and_then(|o| o.as_array())
                                         {
-                                            output.push_str(&format!("// Cell #{i} (outputs)\n"));
+                                            body.push_str(&format!("// Cell #{i} (outputs)\n"));
                                             for output_obj in outputs {
                                                 // Attempt to extract common output types
                                                 if let Some(text) = output_obj
@@ -466,10 +579,10 @@ fn process_file(
                                                 {
                                                     for text_line in text {
                                                         if let Some(line_str) = text_line.as_str() {
-                                                            output.push_str(line_str);
+                                                            body.push_str(line_str);
                                                         }
                                                     }
-                                                    output.push('\n');
+                                                    body.push('\n');
                                                 } else if let Some(data) = output_obj
                                                     .get("data")
                                                     .and_then(|d| d.as_object())
@@ -484,15 +597,15 @@ fn process_file(
                                                                 if let Some(line_str) =
                                                                     text_line.as_str()
                                                                 {
-                                                                    output.push_str(line_str);
+                                                                    body.push_str(line_str);
                                                                 }
                                                             }
-                                                            output.push('\n');
+                                                            body.push('\n');
                                                         } else if let Some(text_str) =
                                                             text_plain.as_str()
                                                         {
-                                                            output.push_str(text_str);
-                                                            output.push('\n');
+                                                            body.push_str(text_str);
+                                                            body.push('\n');
                                                         }
                                                     }
                                                 }
@@ -503,13 +616,13 @@ fn process_file(
                                 "markdown" => {
                                     if let Some(src) = cell.get("source").and_then(|s| s.as_array())
                                     {
-                                        output.push_str(&format!("// Cell #{i} (markdown)\n"));
+                                        body.push_str(&format!("// Cell #{i} (markdown)\n"));
                                         for line_val in src {
                                             if let Some(line_str) = line_val.as_str() {
-                                                output.push_str(line_str);
+                                                body.push_str(line_str);
                                             }
                                         }
-                                        output.push('\n');
+                                        body.push('\n');
                                     }
                                 }
                                 _ => {}
@@ -517,26 +630,28 @@ fn process_file(
                         }
                     }
                 }
-                output.push_str(&format!("\n{delimiter}\n\n"));
+                push_fenced_block(output, delimiter, relative_path_str, &body);
             }
             return Ok(());
         }
     }
 
     // Default case: read the file and include its entire contents.
-    output.push_str(&format!("{delimiter}{relative_path_str}\n"));
-    match std_fs::read_to_string(file) {
-        // Use std_fs
-        Ok(contents) => output.push_str(&contents),
-        Err(e) => {
-            output.push_str(&format!("[Error reading file: {e}]")); // Include error message
-            return Err(e);
-        }
-    }
-    output.push_str(&format!("\n{delimiter}\n\n"));
+    let contents = std_fs::read_to_string(file)?;
+    push_fenced_block(output, delimiter, relative_path_str, &contents);
     Ok(())
 }
 
+/// Appends `body` to `output` as a fenced block labelled with `relative_path`.
+/// The fence is lengthened when the body itself contains delimiter runs, so that
+/// files with embedded code fences survive the prompt and the injection round trip.
+fn push_fenced_block(output: &mut String, delimiter: &str, relative_path: &str, body: &str) {
+    let fence = fence_for_content(body, delimiter);
+    output.push_str(&format!("{fence}{relative_path}\n"));
+    output.push_str(body);
+    output.push_str(&format!("\n{fence}\n\n"));
+}
+
 // A function which returns the directory structurre of a given path
 pub fn directory_peak(dir_path: &str) -> String {
     let path = Path::new(dir_path);
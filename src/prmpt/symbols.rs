@@ -0,0 +1,289 @@
+//! Symbol-level injection: locates a single function, method, class or impl item in
+//! a file with Tree-sitter and replaces only its span, leaving the rest of the file
+//! (including its formatting) untouched. Blocks address a symbol with a header such
+//! as `src/utils.py::parse_config`, `src/run.rs::Generator::run` or
+//! `models.py::User.save`.
+
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser};
+
+/// Languages whose symbols can be replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolLanguage {
+    Python,
+    Rust,
+}
+
+impl SymbolLanguage {
+    /// Picks the language from a file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "py" | "pyi" => Some(SymbolLanguage::Python),
+            "rs" => Some(SymbolLanguage::Rust),
+            _ => None,
+        }
+    }
+
+    fn grammar(self) -> Language {
+        match self {
+            SymbolLanguage::Python => tree_sitter_python::LANGUAGE.into(),
+            SymbolLanguage::Rust => tree_sitter_rust::LANGUAGE.into(),
+        }
+    }
+
+    /// Node kinds that hold nested items, e.g. a class body or an impl block.
+    fn is_body(self, kind: &str) -> bool {
+        match self {
+            SymbolLanguage::Python => kind == "block",
+            SymbolLanguage::Rust => kind == "declaration_list",
+        }
+    }
+
+    /// Whether `line`, the first line of a replacement, starts with the item's
+    /// decorations (decorators, attributes, doc comments).
+    fn is_decoration_line(self, line: &str) -> bool {
+        match self {
+            SymbolLanguage::Python => line.starts_with('@'),
+            SymbolLanguage::Rust => {
+                line.starts_with("#[") || line.starts_with("///") || line.starts_with("/**")
+            }
+        }
+    }
+}
+
+/// Splits a block target into its file path and optional symbol path. The file path
+/// ends at the first `::` preceded by a file name with an extension, so
+/// `src/run.rs::Generator::run` yields `("src/run.rs", Some("Generator::run"))`.
+pub fn split_symbol_target(target: &str) -> (&str, Option<&str>) {
+    let mut search_from = 0;
+    while let Some(offset) = target[search_from..].find("::") {
+        let split = search_from + offset;
+        let (path, symbol) = (&target[..split], &target[split + 2..]);
+        if Path::new(path).extension().is_some() && !symbol.is_empty() {
+            return (path, Some(symbol));
+        }
+        search_from = split + 2;
+    }
+    (target, None)
+}
+
+/// Replaces the item named by `symbol` (segments separated by `::` or `.`, optionally
+/// ending in an impl header) in `source` with `replacement`, re-indented to the
+/// item's position. Returns the reason as `Err` when the symbol is missing or
+/// ambiguous.
+pub fn replace_symbol(
+    language: SymbolLanguage,
+    source: &str,
+    symbol: &str,
+    replacement: &str,
+) -> Result<String, String> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language.grammar())
+        .map_err(|e| format!("failed to load grammar: {e}"))?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| "failed to parse the target file".to_string())?;
+
+    // An impl header (`impl fmt::Display for Foo`) is a single, final segment
+    let impl_start = if symbol.starts_with("impl ") {
+        Some(0)
+    } else {
+        symbol.find("::impl ").map(|i| i + 2)
+    };
+    let (scope, impl_header) = match impl_start {
+        Some(i) => (&symbol[..i], Some(&symbol[i..])),
+        None => (symbol, None),
+    };
+    let segments: Vec<&str> = scope
+        .split("::")
+        .flat_map(|s| s.split('.'))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .chain(impl_header)
+        .collect();
+    if segments.is_empty() {
+        return Err("empty symbol".to_string());
+    }
+    let node = find_symbol(language, tree.root_node(), source, &segments)?;
+
+    // Replace the decorations too when the replacement brings its own
+    let first_line = replacement.trim_start().lines().next().unwrap_or("");
+    let start_node = if language.is_decoration_line(first_line) {
+        decorated_start(language, node, source)
+    } else {
+        node
+    };
+    let start = start_node.start_byte();
+    let end = node.end_byte();
+
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let indent: String = source[line_start..start]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+
+    let mut result = String::with_capacity(source.len() + replacement.len());
+    result.push_str(&source[..start]);
+    result.push_str(&reindent(replacement.trim_end(), &indent));
+    result.push_str(&source[end..]);
+    Ok(result)
+}
+
+/// Resolves `segments` level by level, descending into the bodies of matching items.
+fn find_symbol<'t>(
+    language: SymbolLanguage,
+    root: Node<'t>,
+    source: &str,
+    segments: &[&str],
+) -> Result<Node<'t>, String> {
+    let mut scopes = vec![root];
+    for (depth, segment) in segments.iter().enumerate() {
+        let last = depth + 1 == segments.len();
+        let mut matches = Vec::new();
+        for scope in &scopes {
+            let mut cursor = scope.walk();
+            for child in scope.named_children(&mut cursor) {
+                if let Some(item) = item_matching(language, child, source, segment, last) {
+                    matches.push(item);
+                }
+            }
+        }
+        if last {
+            let path = segments[..=depth].join("::");
+            return match matches.as_slice() {
+                [node] => Ok(*node),
+                [] => Err(format!("symbol `{path}` not found")),
+                _ => Err(format!(
+                    "symbol `{path}` is ambiguous ({} matches)",
+                    matches.len()
+                )),
+            };
+        }
+        scopes = matches
+            .iter()
+            .filter_map(|item| body_of(language, *item))
+            .collect();
+        if scopes.is_empty() {
+            return Err(format!(
+                "symbol `{}` not found",
+                segments[..=depth].join("::")
+            ));
+        }
+    }
+    Err("empty symbol".to_string())
+}
+
+/// Returns the item node for `child` if it defines `name`. Python decorated
+/// definitions resolve to the inner definition. For Rust, impl blocks match their
+/// type name when descending (`Type::method`), and their header (`impl Trait for
+/// Type`) when they are the symbol itself.
+fn item_matching<'t>(
+    language: SymbolLanguage,
+    child: Node<'t>,
+    source: &str,
+    name: &str,
+    last: bool,
+) -> Option<Node<'t>> {
+    let text = |node: Node| node.utf8_text(source.as_bytes()).unwrap_or("");
+    match (language, child.kind()) {
+        (SymbolLanguage::Python, "decorated_definition") => {
+            let definition = child.child_by_field_name("definition")?;
+            item_matching(language, definition, source, name, last)
+        }
+        (SymbolLanguage::Python, "function_definition" | "class_definition") => {
+            (text(child.child_by_field_name("name")?) == name).then_some(child)
+        }
+        (SymbolLanguage::Rust, "impl_item") => {
+            let type_name = text(child.child_by_field_name("type")?);
+            let header = match child.child_by_field_name("trait") {
+                Some(trait_node) => format!("impl {} for {type_name}", text(trait_node)),
+                None => format!("impl {type_name}"),
+            };
+            let matches = if last {
+                normalize(&header) == normalize(name)
+            } else {
+                strip_generics(type_name) == name
+            };
+            matches.then_some(child)
+        }
+        (
+            SymbolLanguage::Rust,
+            "function_item"
+            | "function_signature_item"
+            | "struct_item"
+            | "enum_item"
+            | "union_item"
+            | "trait_item"
+            | "mod_item"
+            | "const_item"
+            | "static_item"
+            | "type_item"
+            | "macro_definition",
+        ) => (text(child.child_by_field_name("name")?) == name).then_some(child),
+        _ => None,
+    }
+}
+
+/// The node holding an item's nested items, if it has any.
+fn body_of(language: SymbolLanguage, item: Node) -> Option<Node> {
+    let body = item.child_by_field_name("body")?;
+    language.is_body(body.kind()).then_some(body)
+}
+
+/// Extends an item backwards over its decorators, attributes and doc comments.
+fn decorated_start<'t>(language: SymbolLanguage, node: Node<'t>, source: &str) -> Node<'t> {
+    match language {
+        SymbolLanguage::Python => match node.parent() {
+            Some(parent) if parent.kind() == "decorated_definition" => parent,
+            _ => node,
+        },
+        SymbolLanguage::Rust => {
+            let mut start = node;
+            while let Some(previous) = start.prev_sibling() {
+                let text = previous.utf8_text(source.as_bytes()).unwrap_or("");
+                let is_decoration = previous.kind() == "attribute_item"
+                    || (previous.kind().ends_with("comment")
+                        && (text.starts_with("///") || text.starts_with("/**")));
+                if !is_decoration {
+                    break;
+                }
+                start = previous;
+            }
+            start
+        }
+    }
+}
+
+/// Re-indents `text` so it can replace an item whose first line starts at `indent`:
+/// the common indentation is removed and `indent` added to every line but the first.
+fn reindent(text: &str, indent: &str) -> String {
+    let common = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let line = line.get(common..).unwrap_or(line.trim_start());
+            if index == 0 || line.is_empty() {
+                line.to_string()
+            } else {
+                format!("{indent}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Collapses whitespace so impl headers compare regardless of formatting.
+fn normalize(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `Foo<T>` -> `Foo`.
+fn strip_generics(type_name: &str) -> &str {
+    type_name.split('<').next().unwrap_or(type_name).trim()
+}
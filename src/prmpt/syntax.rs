@@ -0,0 +1,132 @@
+//! Syntax validation of injected content with Tree-sitter, so truncated model output
+//! (missing closing braces, cut-off strings) is caught before it overwrites a file.
+
+use std::{fmt, path::Path};
+use tree_sitter::{Language, Node, Parser};
+
+/// Languages whose syntax is checked, picked by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxLanguage {
+    Python,
+    Rust,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl SyntaxLanguage {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Some(
+            match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+                "py" | "pyi" => SyntaxLanguage::Python,
+                "rs" => SyntaxLanguage::Rust,
+                "js" | "mjs" | "cjs" | "jsx" => SyntaxLanguage::JavaScript,
+                "ts" | "mts" | "cts" => SyntaxLanguage::TypeScript,
+                "tsx" => SyntaxLanguage::Tsx,
+                "json" => SyntaxLanguage::Json,
+                "yaml" | "yml" => SyntaxLanguage::Yaml,
+                "toml" => SyntaxLanguage::Toml,
+                _ => return None,
+            },
+        )
+    }
+
+    fn grammar(self) -> Language {
+        match self {
+            SyntaxLanguage::Python => tree_sitter_python::LANGUAGE.into(),
+            SyntaxLanguage::Rust => tree_sitter_rust::LANGUAGE.into(),
+            SyntaxLanguage::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            SyntaxLanguage::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            SyntaxLanguage::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            SyntaxLanguage::Json => tree_sitter_json::LANGUAGE.into(),
+            SyntaxLanguage::Yaml => tree_sitter_yaml::LANGUAGE.into(),
+            SyntaxLanguage::Toml => tree_sitter_toml_ng::LANGUAGE.into(),
+        }
+    }
+}
+
+/// The first syntax error in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// 1-based position of the error
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "syntax error at line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Parses `content` as the language of `path` and returns its first error. Files of
+/// other types are not checked.
+pub fn check_syntax(path: &Path, content: &str) -> Option<SyntaxError> {
+    let language = SyntaxLanguage::from_path(path)?;
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut parser = Parser::new();
+    parser.set_language(&language.grammar()).ok()?;
+    let tree = parser.parse(content, None)?;
+    let root = tree.root_node();
+    if !root.has_error() {
+        return None;
+    }
+    let node = first_error(root).unwrap_or(root);
+    let position = node.start_position();
+    let end = content.trim_end().len();
+    if node.is_missing() {
+        return Some(SyntaxError {
+            line: position.row + 1,
+            column: position.column + 1,
+            message: format!("missing `{}`", node.kind()),
+        });
+    }
+    // An error running to the end of the file means the content was cut off
+    if node.end_byte() >= end {
+        let line_start = content[..end].rfind('\n').map_or(0, |i| i + 1);
+        return Some(SyntaxError {
+            line: content[..end].matches('\n').count() + 1,
+            column: content[line_start..end].chars().count() + 1,
+            message: "unexpected end of file".to_string(),
+        });
+    }
+    let text = node.utf8_text(content.as_bytes()).unwrap_or_default();
+    let first_line = text.lines().next().unwrap_or_default().trim();
+    Some(SyntaxError {
+        line: position.row + 1,
+        column: position.column + 1,
+        message: format!("unexpected `{}`", truncate(first_line, 40)),
+    })
+}
+
+/// The first error or missing node in document order.
+fn first_error(node: Node) -> Option<Node> {
+    if node.is_error() || node.is_missing() {
+        // An error node's own children may pinpoint a missing token
+        let mut cursor = node.walk();
+        let missing = node.children(&mut cursor).find(|child| child.is_missing());
+        return missing.or(Some(node));
+    }
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.children(&mut cursor).collect();
+    children
+        .into_iter()
+        .filter(|child| child.has_error())
+        .find_map(first_error)
+}
+
+/// Shortens `text` to `max_chars` characters for error messages.
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}...", &text[..index]),
+        None => text.to_string(),
+    }
+}
@@ -1,3 +1,5 @@
+use crate::prmpt::manifest::Manifest;
+use crate::prmpt::report::InjectionReport;
 use crate::Config;
 use anyhow::Error;
 use std::path::Path; // Using anyhow::Error
@@ -13,6 +15,15 @@ pub trait GenerateOperation {
     /// A `Result` containing a tuple of (generated_output_string, error_messages_vector) on success,
     /// or an `anyhow::Error` on critical failure.
     fn run(&self, config: &Config) -> Result<(String, Vec<String>), Error>;
+
+    /// Runs the generation process and also returns a `Manifest` of the files
+    /// emitted into the prompt, used later to detect stale edits on injection.
+    ///
+    /// The default implementation returns an empty manifest.
+    fn run_with_manifest(&self, config: &Config) -> Result<(String, Vec<String>, Manifest), Error> {
+        let (output, errors) = self.run(config)?;
+        Ok((output, errors, Manifest::default()))
+    }
 }
 
 /// Trait for the 'inject' operation.
@@ -24,7 +35,7 @@ pub trait InjectOperation {
     /// * `repo_path`: Path to the base of the repository where code will be injected.
     ///
     /// # Returns
-    /// An `Ok(())` on successful injection of all parts, or an `anyhow::Error` if
-    /// a critical error occurs or any part of the injection fails.
-    fn inject(&self, input_path: &Path, repo_path: &Path) -> Result<(), Error>;
+    /// An `InjectionReport` describing what happened to every block (including skipped
+    /// ones), or an `anyhow::Error` if a critical error occurs.
+    fn inject(&self, input_path: &Path, repo_path: &Path) -> Result<InjectionReport, Error>;
 }
@@ -0,0 +1,129 @@
+//! Reads chat transcript exports so a model's answer can be injected without copying
+//! it out by hand. Accepted exports hold a list of messages, either at the top level
+//! or under `messages`, `chat_messages` or `contents`, or a ChatGPT-style `mapping`
+//! of message nodes. A message's role comes from `role`, `sender` or `author`, and
+//! its text from `content`, `text` or `parts` (strings or `{"text": ...}` parts).
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+/// Roles treated as the model's answers.
+const ASSISTANT_ROLES: &[&str] = &["assistant", "model", "ai", "bot"];
+
+/// One message of a transcript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptMessage {
+    /// Position in the transcript's list of entries, 0-based, counting entries that
+    /// were left out for having no role.
+    pub index: usize,
+    pub role: String,
+    pub text: String,
+}
+
+impl TranscriptMessage {
+    pub fn is_assistant(&self) -> bool {
+        ASSISTANT_ROLES.contains(&self.role.to_ascii_lowercase().as_str())
+    }
+}
+
+/// Parses a transcript export into its messages, in order. Entries without a role
+/// (e.g. the empty root of a ChatGPT mapping) are left out but keep their place in
+/// the numbering, see [`TranscriptMessage::index`].
+pub fn read_transcript(json: &str) -> Result<Vec<TranscriptMessage>> {
+    let root: Value = serde_json::from_str(json).context("Invalid transcript JSON")?;
+    let entries = message_entries(&root)
+        .context("Transcript has no messages array (expected `messages`, `chat_messages`, `contents` or `mapping`)")?;
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            Some(TranscriptMessage {
+                index,
+                role: role_of(entry)?,
+                text: text_of(entry),
+            })
+        })
+        .collect())
+}
+
+/// Picks the message at position `index` of the transcript (0-based, counting every
+/// entry as in the input), or the last assistant message with text when no index is
+/// given.
+pub fn select_message(messages: &[TranscriptMessage], index: Option<usize>) -> Result<&str> {
+    match index {
+        Some(index) => match messages.iter().find(|message| message.index == index) {
+            Some(message) => Ok(&message.text),
+            None => bail!(
+                "No message at index {index}: the transcript has {} entries with a role",
+                messages.len()
+            ),
+        },
+        None => messages
+            .iter()
+            .rev()
+            .find(|message| message.is_assistant() && !message.text.trim().is_empty())
+            .map(|message| message.text.as_str())
+            .context("Transcript has no assistant message"),
+    }
+}
+
+/// The raw message objects of the supported export shapes.
+fn message_entries(root: &Value) -> Option<Vec<&Value>> {
+    if let Some(messages) = root.as_array() {
+        return Some(messages.iter().collect());
+    }
+    for key in ["messages", "chat_messages", "contents"] {
+        if let Some(messages) = root.get(key).and_then(Value::as_array) {
+            return Some(messages.iter().collect());
+        }
+    }
+    // ChatGPT exports: nodes keyed by id, each with an optional message
+    let mapping = root.get("mapping")?.as_object()?;
+    let mut messages: Vec<&Value> = mapping
+        .values()
+        .filter_map(|node| node.get("message").filter(|m| !m.is_null()))
+        .collect();
+    messages.sort_by(|a, b| {
+        let time = |m: &Value| m.get("create_time").and_then(Value::as_f64).unwrap_or(0.0);
+        time(a).total_cmp(&time(b))
+    });
+    Some(messages)
+}
+
+fn role_of(entry: &Value) -> Option<String> {
+    let role = entry
+        .get("role")
+        .or_else(|| entry.get("sender"))
+        .or_else(|| entry.get("author").map(|a| a.get("role").unwrap_or(a)))?;
+    role.as_str().map(str::to_string)
+}
+
+fn text_of(entry: &Value) -> String {
+    ["content", "text", "parts"]
+        .into_iter()
+        .filter_map(|key| entry.get(key))
+        .map(flatten_text)
+        .find(|text| !text.is_empty())
+        .unwrap_or_default()
+}
+
+/// Joins the text of a string, an array of parts, or an object holding `parts` or
+/// `text`. Non-text parts such as images are ignored.
+fn flatten_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .map(flatten_text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Object(object) => ["text", "parts", "content"]
+            .into_iter()
+            .filter_map(|key| object.get(key))
+            .map(flatten_text)
+            .find(|text| !text.is_empty())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
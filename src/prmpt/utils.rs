@@ -1,8 +1,13 @@
-//! Contains utility functions for directory structure visualization.
-//! The ignore logic has been unified with the main processing in run.rs.
+//! Contains utility functions for directory structure visualization, code fences
+//! and reading inputs. The ignore logic has been unified with the main processing in run.rs.
 
+use anyhow::{Context, Result};
 use glob::Pattern;
-use std::path::Path;
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
 use walkdir::WalkDir;
 
 /// Recursively builds a textual structure visualization for a directory.
@@ -116,3 +121,68 @@ pub fn process_directory_structure(
         }
     }
 }
+
+/// Returns the fence character when `delimiter` is a run of a single backtick or
+/// tilde character (e.g. "```" or "~~~"), which allows CommonMark-style longer fences.
+pub fn fence_char(delimiter: &str) -> Option<char> {
+    let first = delimiter.chars().next()?;
+    if (first == '`' || first == '~') && delimiter.chars().all(|c| c == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+/// Picks a fence for wrapping `contents`. For backtick or tilde delimiters the fence
+/// is made longer than the longest run of that character inside the contents, so
+/// embedded code fences (e.g. in Markdown files or doc comments) cannot close it.
+/// Other delimiters are returned unchanged.
+pub fn fence_for_content(contents: &str, delimiter: &str) -> String {
+    let Some(fence_char) = fence_char(delimiter) else {
+        return delimiter.to_string();
+    };
+
+    let mut longest_run = 0;
+    let mut current_run = 0;
+    for c in contents.chars() {
+        if c == fence_char {
+            current_run += 1;
+            longest_run = longest_run.max(current_run);
+        } else {
+            current_run = 0;
+        }
+    }
+
+    let fence_len = delimiter.chars().count().max(longest_run + 1);
+    fence_char.to_string().repeat(fence_len)
+}
+
+/// Compiles glob patterns from the command line or configuration, naming the
+/// invalid one in the error.
+pub fn compile_globs<S: AsRef<str>>(globs: &[S]) -> Result<Vec<Pattern>> {
+    globs
+        .iter()
+        .map(|g| {
+            let g = g.as_ref();
+            Pattern::new(g).with_context(|| format!("Invalid glob pattern: '{g}'"))
+        })
+        .collect()
+}
+
+/// True when `path` is `-`, which stands for stdin.
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Reads a text input from `path`, or from stdin when the path is `-`.
+pub fn read_input(path: &Path) -> Result<String> {
+    if is_stdin(path) {
+        let mut contents = String::new();
+        io::stdin()
+            .read_to_string(&mut contents)
+            .context("Failed to read input from stdin")?;
+        return Ok(contents);
+    }
+    fs::read_to_string(path)
+        .with_context(|| format!("Failed to read input file: '{}'", path.display()))
+}
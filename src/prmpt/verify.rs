@@ -0,0 +1,158 @@
+//! Verify-and-rollback for injection: runs a check command (e.g. `cargo test`) after
+//! the blocks are applied and restores every touched file if it fails, optionally
+//! writing a follow-up prompt with the failure and the rejected files.
+
+use crate::prmpt::report::CommandOutcome;
+use crate::prmpt::utils::fence_for_content;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Runs `command` through the platform shell in `dir`. Unlike hooks, the command
+/// comes from the user rather than model output, so shell syntax such as `&&` is
+/// allowed.
+pub fn run_verify(command: &str, dir: &Path) -> CommandOutcome {
+    let mut outcome = CommandOutcome::new(command, None);
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    info!("Running verify command `{command}`");
+    match shell.arg(command).current_dir(dir).output() {
+        Ok(output) => {
+            outcome.exit_code = output.status.code();
+            outcome.stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+            outcome.stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            if !outcome.success() {
+                warn!(
+                    "Verify command `{command}` failed with status {:?}",
+                    outcome.exit_code
+                );
+            }
+        }
+        Err(e) => {
+            outcome.stderr = e.to_string();
+            warn!("Failed to run verify command `{command}`: {e}");
+        }
+    }
+    outcome
+}
+
+/// A file's state before injection.
+#[derive(Debug)]
+struct SavedFile {
+    contents: Vec<u8>,
+    permissions: fs::Permissions,
+}
+
+/// Contents of files as they were before injection, keyed by absolute path.
+/// `None` records that a file did not exist.
+#[derive(Debug, Default)]
+pub struct Backup {
+    files: BTreeMap<PathBuf, Option<SavedFile>>,
+    /// Parent directories that did not exist yet, so injection may create them
+    missing_dirs: BTreeSet<PathBuf>,
+}
+
+impl Backup {
+    /// Saves the current state of `path`, unless it was already saved.
+    pub fn save(&mut self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(missing) = dir.filter(|dir| !dir.exists()) {
+            self.missing_dirs.insert(missing.to_path_buf());
+            dir = missing.parent();
+        }
+        self.files.entry(path.to_path_buf()).or_insert_with(|| {
+            let contents = fs::read(path).ok()?;
+            let permissions = fs::metadata(path).ok()?.permissions();
+            Some(SavedFile {
+                contents,
+                permissions,
+            })
+        });
+    }
+
+    /// Puts every path in `paths` back in its saved state: rewrites files that
+    /// existed and removes files that did not, along with the directories created
+    /// for them once they are empty. Paths that were never saved are left alone.
+    pub fn restore(&self, paths: &[PathBuf]) -> Result<()> {
+        for path in paths {
+            match self.files.get(path) {
+                Some(Some(saved)) => {
+                    fs::write(path, &saved.contents)
+                        .with_context(|| format!("Failed to restore {path:?}"))?;
+                    fs::set_permissions(path, saved.permissions.clone())
+                        .with_context(|| format!("Failed to restore permissions of {path:?}"))?;
+                }
+                Some(None) => {
+                    if path.exists() {
+                        fs::remove_file(path)
+                            .with_context(|| format!("Failed to remove {path:?}"))?;
+                    }
+                }
+                None => warn!("No backup of {path:?}; leaving it as is."),
+            }
+            info!("Restored {path:?}");
+        }
+        // Deepest first; directories that gained other files are kept
+        for dir in self.missing_dirs.iter().rev() {
+            if fs::remove_dir(dir).is_ok() {
+                info!("Removed created directory {dir:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes a prompt asking the model to fix the failure, containing the command
+/// output and the touched files as they were after injection (`None` for files the
+/// injection deleted). Files are fenced like generated prompts so the answer can be
+/// injected again.
+pub fn write_followup_prompt(
+    path: &Path,
+    outcome: &CommandOutcome,
+    files: &BTreeMap<String, Option<String>>,
+    delimiter: &str,
+) -> Result<()> {
+    let mut prompt = format!(
+        "After applying your changes, `{}` failed with exit code {}:\n\n",
+        outcome.command,
+        outcome
+            .exit_code
+            .map_or_else(|| "unknown".to_string(), |code| code.to_string())
+    );
+    let output = format!("{}{}", outcome.stdout, outcome.stderr);
+    let fence = fence_for_content(&output, delimiter);
+    prompt.push_str(&format!("{fence}\n{}\n{fence}\n\n", output.trim_end()));
+
+    prompt.push_str("These are the files you changed, as you left them:\n\n");
+    for (relative_path, contents) in files {
+        match contents {
+            Some(contents) => {
+                let fence = fence_for_content(contents, delimiter);
+                prompt.push_str(&format!(
+                    "{fence}{relative_path}\n{}\n{fence}\n\n",
+                    contents.trim_end_matches('\n')
+                ));
+            }
+            None => prompt.push_str(&format!("DELETE {relative_path}\n\n")),
+        }
+    }
+    prompt.push_str(
+        "The changes were rolled back. Fix the failure and reply with the complete corrected files.\n",
+    );
+
+    fs::write(path, prompt)
+        .with_context(|| format!("Failed to write follow-up prompt: '{}'", path.display()))
+}
@@ -1,5 +1,8 @@
 use insta::assert_yaml_snapshot;
-use prmpt::{run_and_write, Config, GenerateOperation, Generator};
+use prmpt::{
+    extract_python_signatures, extract_python_signatures_with_comments, extract_python_stub,
+    run_and_write, CommentKind, Config, GenerateOperation, Generator,
+};
 use std::path::PathBuf;
 
 // Helper function to construct path to test_repos
@@ -22,8 +25,13 @@ fn test_sample_project_1_default_snapshot() {
         language: Some("python".to_string()), // Explicitly set for clarity
         docs_comments_only: Some(false),      // Default behavior
         docs_ignore: Some(vec![]),
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(true), // Test .gitignore processing
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
         prompts: None,
     };
 
@@ -60,8 +68,13 @@ fn test_sample_project_1_docs_only_snapshot() {
         language: Some("python".to_string()),
         docs_comments_only: Some(true), // Test docs_comments_only feature
         docs_ignore: Some(vec![]),
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(true),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
         prompts: None,
     };
 
@@ -183,8 +196,13 @@ fn output_file_ignorance_snapshot() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false), // Focus on *.out and curly.yaml ignores
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
 
     let generator = Generator;
@@ -220,8 +238,13 @@ fn language_specific_ignore_snapshot() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
 
     let generator = Generator;
@@ -250,8 +273,13 @@ fn language_specific_ignore_javascript_snapshot() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
 
     let generator = Generator;
@@ -283,8 +311,13 @@ fn language_specific_ignore_typescript_snapshot() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
 
     let generator = Generator;
@@ -317,8 +350,13 @@ fn run_and_write_python_generates_file() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
     let generator = Generator;
     let (expected, _) = generator.run(&config).unwrap();
@@ -341,8 +379,13 @@ fn run_and_write_rust_generates_file() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
     let generator = Generator;
     let (expected, _) = generator.run(&config).unwrap();
@@ -365,8 +408,13 @@ fn run_and_write_javascript_generates_file() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
     let generator = Generator;
     let (expected, _) = generator.run(&config).unwrap();
@@ -389,8 +437,13 @@ fn run_and_write_typescript_generates_file() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
     let generator = Generator;
     let (expected, _) = generator.run(&config).unwrap();
@@ -472,8 +525,13 @@ fn gitignore_without_git_respected() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(true),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
 
     let generator = Generator;
@@ -500,8 +558,13 @@ fn gitignore_disabled_ignores_nothing() {
         prompts: None,
         docs_comments_only: Some(false),
         docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
         use_gitignore: Some(false),
         display_outputs: Some(false),
+        manifest: None,
+        inject: None,
     };
 
     let generator = Generator;
@@ -515,3 +578,250 @@ fn gitignore_disabled_ignores_nothing() {
         Err(e) => panic!("Failed to run generator: {e:?}"),
     }
 }
+
+#[test]
+fn python_docs_only_keeps_full_signatures() {
+    let source = r#"from dataclasses import dataclass, field
+
+__all__ = ["Point"]
+
+MAX_RETRIES: int = 3
+TIMEOUT: float = (
+    1.5
+)
+UNTYPED = 1
+
+@dataclass(frozen=True)
+class Point[T](Base, metaclass=Meta):
+    """A point."""
+    x: T
+    tags: list[str] = field(default_factory=list)
+    counter = 0
+    """How many points exist."""
+    cache = {}
+
+    async def fetch[U](
+        self,
+        url: str,
+    ) -> dict[str, U]:
+        """Fetch it."""
+        return {}
+"#;
+    assert_eq!(
+        extract_python_signatures_with_comments(source, CommentKind::ALL),
+        r#"__all__ = ["Point"]
+MAX_RETRIES: int = 3
+TIMEOUT: float = ...
+
+@dataclass(frozen=True)
+class Point[T](Base, metaclass=Meta):
+    """A point."""
+    x: T
+    tags: list[str] = field(default_factory=list)
+    counter = 0
+    """How many points exist."""
+
+    async def fetch[U](
+        self,
+        url: str,
+    ) -> dict[str, U]:
+        """Fetch it."""
+"#
+    );
+}
+
+#[test]
+fn python_docs_only_keeps_empty_docstrings() {
+    let source = r#"""""""
+
+def f():
+    """"""
+    return 1
+
+class C:
+    ''''''
+
+    def g(self):
+        ""
+"#;
+    let expected = r#"""""""
+
+def f():
+    """"""
+
+class C:
+    ''''''
+    def g(self):
+        ""
+"#;
+    assert_eq!(
+        extract_python_signatures_with_comments(source, CommentKind::ALL),
+        expected
+    );
+    assert_eq!(extract_python_stub(source, CommentKind::ALL), expected);
+}
+
+#[test]
+fn python_docs_only_keeps_comments_by_kind() {
+    let source = r#"#!/usr/bin/env python
+# Copyright Example Corp.
+
+import os  # NOTE: only for paths
+
+# Unrelated remark
+
+# Greets people.
+class Greeter:  # noqa
+    def greet(self):
+        # plain comment
+        if True:
+            pass  # TODO: handle False
+        return 1
+"#;
+    assert_eq!(
+        extract_python_signatures_with_comments(source, CommentKind::ALL),
+        r#"# Copyright Example Corp.
+
+# NOTE: only for paths
+
+# Greets people.
+class Greeter:
+    def greet(self):
+        # TODO: handle False
+"#
+    );
+    assert_eq!(
+        extract_python_signatures_with_comments(source, &[CommentKind::Block]),
+        "# Greets people.\nclass Greeter:\n    def greet(self):\n"
+    );
+    assert_eq!(
+        extract_python_signatures(source),
+        "class Greeter:\n    def greet(self):\n"
+    );
+}
+
+#[test]
+fn python_stubs_keep_optional_imports_and_forward_references() {
+    let source = r#"from collections.abc import Iterator
+import json
+
+try:
+    import numpy as np
+except ImportError:
+    np = None
+    import array
+
+def rows(data: "np.ndarray") -> "Iterator[int]":
+    """Yield the rows."""
+    yield from data
+"#;
+    assert_eq!(
+        extract_python_stub(source, CommentKind::ALL),
+        r#"from collections.abc import Iterator
+import numpy as np
+
+def rows(data: "np.ndarray") -> "Iterator[int]":
+    """Yield the rows."""
+"#
+    );
+}
+
+#[test]
+fn python_stubs_are_written_to_a_directory() {
+    let source = r#"from typing import TYPE_CHECKING, Any, Generic, NewType, TypeVar
+from dataclasses import dataclass, field
+import os
+
+if TYPE_CHECKING:
+    from pathlib import Path
+
+T = TypeVar("T")
+UserId = NewType("UserId", int)
+MaybePath = Path | None
+LIMIT = 10
+
+@dataclass
+class Point(Generic[T]):
+    x: int = 0
+    path: Path = field(default=None)
+
+    def norm(self, **kw: Any) -> float:
+        def helper():
+            pass
+        return 0.0
+
+class Empty:
+    pass
+"#;
+    assert_eq!(
+        extract_python_stub(source, CommentKind::ALL),
+        r#"from typing import Any, Generic, NewType, TypeVar
+from dataclasses import dataclass
+from pathlib import Path
+T = TypeVar("T")
+UserId = NewType("UserId", int)
+MaybePath = Path | None
+
+@dataclass
+class Point(Generic[T]):
+    x: int = ...
+    path: Path = ...
+
+    def norm(self, **kw: Any) -> float:
+        ...
+
+class Empty:
+    ...
+"#
+    );
+
+    // Stubs are written by `run_and_write`, into a directory inside the repository
+    let dir = tempfile::tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    std::fs::create_dir_all(repo.join("pkg")).unwrap();
+    std::fs::write(
+        repo.join("pkg/models.py"),
+        "class Model:\n    def save(self):\n        return 1\n",
+    )
+    .unwrap();
+    let stubs_dir = repo.join("typings");
+    let output = dir.path().join("prompt.out");
+    let config = Config {
+        path: Some(repo.to_string_lossy().to_string()),
+        output: Some(output.to_string_lossy().to_string()),
+        ignore: Some(vec![]),
+        delimiter: Some("```".to_string()),
+        language: Some("python".to_string()),
+        docs_comments_only: Some(true),
+        docs_ignore: Some(vec![]),
+        docs_comment_kinds: None,
+        docs_stubs: Some(true),
+        stubs_dir: Some(stubs_dir.to_string_lossy().to_string()),
+        use_gitignore: Some(true),
+        display_outputs: Some(false),
+        manifest: None,
+        inject: None,
+        prompts: None,
+    };
+    let (prompt, _) = Generator.run(&config).unwrap();
+    assert!(!stubs_dir.exists(), "generation alone writes no stubs");
+    run_and_write(&Generator, &config).unwrap();
+    let stub = std::fs::read_to_string(stubs_dir.join("pkg/models.pyi")).unwrap();
+    assert_eq!(stub, "class Model:\n    def save(self):\n        ...\n");
+    assert!(prompt.contains(&stub));
+
+    // A second run does not pick up the stubs it wrote
+    run_and_write(&Generator, &config).unwrap();
+    let prompt = std::fs::read_to_string(&output).unwrap();
+    assert!(!prompt.contains("typings"), "{prompt}");
+
+    // A stub that cannot be written does not drop the file from the prompt
+    std::fs::remove_dir_all(&stubs_dir).unwrap();
+    std::fs::write(&stubs_dir, "not a directory").unwrap();
+    run_and_write(&Generator, &config).unwrap();
+    let prompt = std::fs::read_to_string(&output).unwrap();
+    assert!(
+        prompt.contains("```pkg/models.py\nclass Model:"),
+        "{prompt}"
+    );
+}
@@ -1,7 +1,57 @@
-use prmpt::{InjectOperation, Injector};
+use prmpt::{
+    load_config_from, parse_response, run_and_write, BlockAction, BlockOperation, Config,
+    GenerateOperation, Generator, GitOptions, InjectOperation, Injector, Manifest, ParseOptions,
+    PathMarker, PlaceholderPolicy, ResponseFormat, Reviewer, WritePolicy, DEFAULT_CONFIG_KEY,
+};
 use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use tempfile::tempdir;
 
+/// Plain generation config for a temporary repository
+fn generate_config(repo: &Path) -> Config {
+    Config {
+        path: Some(repo.to_string_lossy().to_string()),
+        output: None,
+        ignore: None,
+        delimiter: Some("```".to_string()),
+        language: None,
+        prompts: None,
+        docs_comments_only: Some(false),
+        docs_ignore: None,
+        docs_comment_kinds: None,
+        docs_stubs: None,
+        stubs_dir: None,
+        use_gitignore: Some(false),
+        display_outputs: Some(false),
+        manifest: None,
+        inject: None,
+    }
+}
+
+/// Runs git in `repo` and returns its stdout
+fn git(repo: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {args:?} failed: {output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Creates a git repository at `repo` with everything in it committed
+fn init_git_repo(repo: &Path) {
+    git(repo, &["init", "-q"]);
+    git(repo, &["config", "user.name", "prmpt"]);
+    git(repo, &["config", "user.email", "prmpt@example.com"]);
+    git(repo, &["config", "commit.gpgsign", "false"]);
+    git(repo, &["add", "-A"]);
+    git(repo, &["commit", "-q", "-m", "initial"]);
+}
+
 #[test]
 fn inject_plain_path() {
     let dir = tempdir().unwrap();
@@ -12,7 +62,7 @@ fn inject_plain_path() {
     let input = repo.join("input.in");
     fs::write(&input, "src/lib.rs\n```rust\nfn new_fn() {}\n```\n").unwrap();
 
-    let injector = Injector;
+    let injector = Injector::default();
     injector.inject(&input, repo).unwrap();
 
     let contents = fs::read_to_string(repo.join("src/lib.rs")).unwrap();
@@ -29,7 +79,7 @@ fn inject_backticked_path() {
     let input = repo.join("input.in");
     fs::write(&input, "### `src/lib.rs`\n```rust\nfn added() {}\n```\n").unwrap();
 
-    let injector = Injector;
+    let injector = Injector::default();
     injector.inject(&input, repo).unwrap();
 
     let contents = fs::read_to_string(repo.join("src/lib.rs")).unwrap();
@@ -46,7 +96,7 @@ fn inject_fence_with_path() {
     let input = repo.join("input.in");
     fs::write(&input, "```src/lib.rs\nfn replaced() {}\n```\n").unwrap();
 
-    let injector = Injector;
+    let injector = Injector::default();
     injector.inject(&input, repo).unwrap();
 
     let contents = fs::read_to_string(repo.join("src/lib.rs")).unwrap();
@@ -63,9 +113,2037 @@ fn inject_fence_with_language_and_path() {
     let input = repo.join("input.in");
     fs::write(&input, "```rust src/lib.rs\nfn update() {}\n```\n").unwrap();
 
-    let injector = Injector;
+    let injector = Injector::default();
     injector.inject(&input, repo).unwrap();
 
     let contents = fs::read_to_string(repo.join("src/lib.rs")).unwrap();
     assert!(contents.contains("update"));
 }
+
+#[test]
+fn inject_delete_directive() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("src/old.rs"), "fn old() {}\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(&input, "DELETE `src/old.rs`\n").unwrap();
+
+    let injector = Injector::default();
+    injector.inject(&input, repo).unwrap();
+
+    assert!(!repo.join("src/old.rs").exists());
+}
+
+#[test]
+fn inject_rename_directive_then_write() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("src/utils.rs"), "fn helper() {}\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "RENAME src/utils.rs -> src/helpers/mod.rs\n\nsrc/helpers/extra.rs\n```rust\nfn extra() {}\n```\n",
+    )
+    .unwrap();
+
+    let injector = Injector::default();
+    injector.inject(&input, repo).unwrap();
+
+    assert!(!repo.join("src/utils.rs").exists());
+    let moved = fs::read_to_string(repo.join("src/helpers/mod.rs")).unwrap();
+    assert!(moved.contains("helper"));
+    assert!(repo.join("src/helpers/extra.rs").exists());
+}
+
+#[test]
+fn inject_delete_outside_repo_is_skipped() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(dir.path().join("outside.rs"), "fn keep() {}\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(&input, "DELETE ../outside.rs\n").unwrap();
+
+    let injector = Injector::default();
+    injector.inject(&input, &repo).unwrap();
+
+    assert!(dir.path().join("outside.rs").exists());
+}
+
+#[test]
+fn inject_longer_fence_keeps_inner_fences() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "````README.md\n# Title\n\n```rust\nfn main() {}\n```\n\nMore text\n````\n",
+    )
+    .unwrap();
+
+    let injector = Injector::default();
+    injector.inject(&input, repo).unwrap();
+
+    let contents = fs::read_to_string(repo.join("README.md")).unwrap();
+    assert!(contents.contains("```rust\nfn main() {}\n```"));
+    assert!(contents.contains("More text"));
+}
+
+#[test]
+fn inject_custom_delimiter() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+
+    let input = repo.join("input.in");
+    fs::write(&input, "~~~src/lib.rs\n```\nfn doc() {}\n~~~\n").unwrap();
+
+    // The content is not valid Rust; only the delimiter handling matters here
+    let injector = Injector {
+        delimiter: Some("~~~".to_string()),
+        allow_syntax_errors: true,
+        ..Default::default()
+    };
+    injector.inject(&input, repo).unwrap();
+
+    let contents = fs::read_to_string(repo.join("src/lib.rs")).unwrap();
+    assert!(contents.starts_with("```\nfn doc() {}"));
+}
+
+#[test]
+fn generated_fence_survives_round_trip() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    let readme = "# Docs\n\n````text\nnested ```\n````\n";
+    fs::write(repo.join("README.md"), readme).unwrap();
+
+    let (output, _) = Generator.run(&generate_config(&repo)).unwrap();
+    assert!(output.contains("`````README.md\n"));
+
+    fs::write(repo.join("README.md"), "stale\n").unwrap();
+    let input = dir.path().join("prompt.in");
+    fs::write(&input, &output).unwrap();
+    Injector::default().inject(&input, &repo).unwrap();
+
+    let contents = fs::read_to_string(repo.join("README.md")).unwrap();
+    assert_eq!(contents.trim_end(), readme.trim_end());
+}
+
+#[test]
+fn generate_then_inject_is_byte_exact() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    let files: &[(&str, &[u8])] = &[
+        ("crlf.txt", b"first\r\nsecond\r\n"),
+        ("bom.py", b"\xEF\xBB\xBFprint('hi')\n"),
+        ("no_newline.rs", b"fn main() {}"),
+        ("blank_lines.md", b"# Title\n\n\n"),
+        ("mixed.txt", b"a\r\nb\nc\r\n"),
+        ("script.sh", b"#!/bin/sh\necho hi\n"),
+    ];
+    for (name, bytes) in files {
+        fs::write(repo.join(name), bytes).unwrap();
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(repo.join("script.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let (output, _) = Generator.run(&generate_config(&repo)).unwrap();
+    let input = dir.path().join("prompt.in");
+    fs::write(&input, &output).unwrap();
+    Injector::default().inject(&input, &repo).unwrap();
+
+    for (name, bytes) in files {
+        assert_eq!(&fs::read(repo.join(name)).unwrap(), bytes, "{name} changed");
+    }
+}
+
+#[test]
+fn inject_keeps_target_conventions() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(repo.join("win.txt"), b"\xEF\xBB\xBFold\r\nlines\r\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(repo.join("win.txt"), fs::Permissions::from_mode(0o750)).unwrap();
+    }
+
+    let input = repo.join("input.in");
+    fs::write(&input, "win.txt\n```\nnew\ncontent\n```\n").unwrap();
+    Injector::default().inject(&input, repo).unwrap();
+
+    assert_eq!(
+        fs::read(repo.join("win.txt")).unwrap(),
+        b"\xEF\xBB\xBFnew\r\ncontent\r\n"
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(repo.join("win.txt"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+}
+
+#[test]
+fn inject_new_file_ends_with_newline() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+
+    let input = repo.join("input.in");
+    fs::write(&input, "src/new.rs\n```rust\nfn fresh() {}\n```\n").unwrap();
+    Injector::default().inject(&input, repo).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(repo.join("src/new.rs")).unwrap(),
+        "fn fresh() {}\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn inject_runs_matching_hooks() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+
+    let config: Config = serde_yaml::from_str(
+        "inject:\n  hooks:\n    \"*.rs\": cp {file} {file}.formatted\n    \"*.py\": cp {file} {file}.py_hook\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        hooks: config.inject.unwrap().hooks,
+        ..Default::default()
+    };
+
+    let input = repo.join("input.in");
+    fs::write(&input, "src/lib.rs\n```rust\nfn hooked() {}\n```\n").unwrap();
+    injector.inject(&input, repo).unwrap();
+
+    assert!(repo.join("src/lib.rs.formatted").exists());
+    assert!(!repo.join("src/lib.rs.py_hook").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn inject_summary_shows_output_of_failed_hooks() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    let hooks = [
+        ("*.rs".to_string(), "cat {file}.missing".to_string()),
+        ("*.txt".to_string(), "cat {file}".to_string()),
+    ];
+    let injector = Injector {
+        hooks: Some(hooks.into_iter().collect()),
+        ..Default::default()
+    };
+
+    let input = dir.path().join("input.in");
+    fs::write(
+        &input,
+        "src/lib.rs\n```rust\nfn hooked() {}\n```\nnotes.txt\n```\nquiet\n```\n",
+    )
+    .unwrap();
+    let report = injector.inject(&input, repo).unwrap();
+
+    assert_eq!(
+        report.hooks[0].file.as_deref(),
+        Some(Path::new("src/lib.rs"))
+    );
+    let summary = report.to_string();
+    assert!(summary.contains("hook failed (1): cat src/lib.rs.missing\n  cat: src/lib.rs.missing"));
+    // Output of successful hooks stays out of the summary
+    assert!(summary.contains("hook ok: cat notes.txt\n"));
+    assert!(!summary.contains("  quiet"));
+}
+
+#[test]
+fn inject_skips_files_changed_since_generation() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("edited.rs"), "fn original() {}\n").unwrap();
+    fs::write(repo.join("untouched.rs"), "fn original() {}\n").unwrap();
+
+    let (_, _, manifest) = Generator
+        .run_with_manifest(&generate_config(&repo))
+        .unwrap();
+    assert_eq!(manifest.files.len(), 2);
+
+    // Local edit while waiting for the model
+    fs::write(repo.join("edited.rs"), "fn my_local_work() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "edited.rs\n```rust\nfn from_model() {}\n```\n\nuntouched.rs\n```rust\nfn from_model() {}\n```\n",
+    )
+    .unwrap();
+
+    let injector = Injector {
+        manifest: Some(manifest.clone()),
+        ..Default::default()
+    };
+    injector.inject(&input, &repo).unwrap();
+    assert!(fs::read_to_string(repo.join("edited.rs"))
+        .unwrap()
+        .contains("my_local_work"));
+    assert!(fs::read_to_string(repo.join("untouched.rs"))
+        .unwrap()
+        .contains("from_model"));
+
+    let injector = Injector {
+        manifest: Some(manifest),
+        force: true,
+        ..Default::default()
+    };
+    injector.inject(&input, &repo).unwrap();
+    assert!(fs::read_to_string(repo.join("edited.rs"))
+        .unwrap()
+        .contains("from_model"));
+}
+
+#[test]
+fn run_and_write_writes_manifest_next_to_output() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(repo.join("lib.rs"), "fn lib() {}\n").unwrap();
+
+    let output = repo.join("prompt.out");
+    let mut config = generate_config(repo);
+    config.output = Some(output.to_string_lossy().to_string());
+    config.manifest = Some(true);
+    run_and_write(&Generator, &config).unwrap();
+
+    let manifest_path = Manifest::path_for_output(&output.to_string_lossy());
+    let manifest = Manifest::load(&manifest_path).unwrap();
+    assert!(manifest.get(Path::new("lib.rs")).is_some());
+
+    // A second run must not include the manifest itself in the prompt
+    let (prompt, _) = Generator.run(&config).unwrap();
+    assert!(!prompt.contains("manifest.json"));
+}
+
+#[test]
+fn inject_merges_local_edits_with_model_changes() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("lib.rs"), "fn a() {}\n\nfn b() {}\n\nfn c() {}\n").unwrap();
+
+    let (_, _, manifest) = Generator
+        .run_with_manifest(&generate_config(&repo))
+        .unwrap();
+
+    // Local edit at the top while the model changes the bottom
+    fs::write(
+        repo.join("lib.rs"),
+        "fn a_local() {}\n\nfn b() {}\n\nfn c() {}\n",
+    )
+    .unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "lib.rs\n```rust\nfn a() {}\n\nfn b() {}\n\nfn c_model() {}\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        manifest: Some(manifest),
+        ..Default::default()
+    };
+    injector.inject(&input, &repo).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(repo.join("lib.rs")).unwrap(),
+        "fn a_local() {}\n\nfn b() {}\n\nfn c_model() {}\n"
+    );
+}
+
+#[test]
+fn inject_writes_conflict_markers_on_overlapping_edits() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("lib.rs"), "fn shared() {}\n").unwrap();
+
+    let (_, _, manifest) = Generator
+        .run_with_manifest(&generate_config(&repo))
+        .unwrap();
+    fs::write(repo.join("lib.rs"), "fn local() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(&input, "lib.rs\n```rust\nfn model() {}\n```\n").unwrap();
+    let injector = Injector {
+        manifest: Some(manifest),
+        ..Default::default()
+    };
+    injector.inject(&input, &repo).unwrap();
+
+    let contents = fs::read_to_string(repo.join("lib.rs")).unwrap();
+    assert!(
+        contents.contains("<<<<<<< ours\nfn local() {}\n=======\nfn model() {}\n>>>>>>> theirs")
+    );
+}
+
+#[test]
+fn inject_report_lists_every_block() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("same.rs"), "fn same() {}\n").unwrap();
+    fs::write(repo.join("old.rs"), "fn old() {}\n").unwrap();
+    fs::write(repo.join("gone.rs"), "fn gone() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "new.rs\n```rust\nfn new() {}\n```\n\
+        same.rs\n```rust\nfn same() {}\n```\n\
+        old.rs\n```rust\nfn old_and_longer() {}\n```\n\
+        DELETE gone.rs\n\
+        ```rust\nfn orphan() {}\n```\n\
+        ../outside.rs\n```rust\nfn escape() {}\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, &repo).unwrap();
+
+    let summary: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| (b.start_line, b.end_line, b.action, b.byte_delta))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1, 4, BlockAction::Created, 12),
+            (5, 8, BlockAction::Unchanged, 0),
+            (9, 12, BlockAction::Modified, 11),
+            (13, 13, BlockAction::Deleted, -13),
+            (14, 16, BlockAction::Skipped, 0),
+            (17, 20, BlockAction::Skipped, 0),
+        ]
+    );
+    assert_eq!(
+        report.blocks[4].reason.as_deref(),
+        Some("code block closed without a file path")
+    );
+    assert_eq!(
+        report.blocks[5].reason.as_deref(),
+        Some("path contains `..`")
+    );
+    assert!(report.has_skipped());
+    assert_eq!(
+        report.written_paths(),
+        vec![Path::new("new.rs"), Path::new("old.rs")]
+    );
+}
+
+#[test]
+fn inject_report_serializes_to_json() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(repo.join("a.rs"), "fn a() {}\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(&input, "RENAME a.rs -> b.rs\n").unwrap();
+    let report = Injector::default().inject(&input, repo).unwrap();
+
+    let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+    let block = &json["blocks"][0];
+    assert_eq!(block["action"], "renamed");
+    assert_eq!(block["resolved_path"], "a.rs");
+    assert_eq!(block["destination"], "b.rs");
+    assert_eq!(block["start_line"], 1);
+    assert!(!report.has_skipped());
+    assert!(report.to_string().contains("renamed"));
+}
+
+#[test]
+fn inject_only_and_exclude_filter_blocks() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(repo.join("src")).unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "src/a.rs\n```rust\nfn a() {}\n```\n\
+        src/b.rs\n```rust\nfn b() {}\n```\n\
+        README.md\n```markdown\n# readme\n```\n\
+        repo/src/c.rs\n```rust\nfn c() {}\n```\n\
+        Cargo.lock\n```\n# lock\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        only: vec!["src/*.rs".to_string()],
+        exclude: vec!["**/b.rs".to_string()],
+        ..Default::default()
+    };
+    let report = injector.inject(&input, &repo).unwrap();
+
+    assert!(repo.join("src/a.rs").exists());
+    assert!(!repo.join("src/b.rs").exists());
+    assert!(!repo.join("README.md").exists());
+    // Filters match the resolved path, not the one written in the response
+    assert!(repo.join("src/c.rs").exists());
+    assert_eq!(report.count(BlockAction::Skipped), 3);
+    assert_eq!(
+        report.blocks[1].reason.as_deref(),
+        Some("filtered out by --only/--exclude")
+    );
+    // Filtered out before the policy would have denied it
+    assert_eq!(
+        report.blocks[4].reason.as_deref(),
+        Some("filtered out by --only/--exclude")
+    );
+}
+
+#[test]
+fn inject_reviewed_applies_only_accepted_blocks() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("a.rs"), "fn a() {}\n").unwrap();
+    fs::write(repo.join("b.rs"), "fn b() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "a.rs\n```rust\nfn a_good() {}\n```\n\
+        b.rs\n```rust\nfn b_bad() {}\n```\n\
+        c.rs\n```rust\nfn c() {}\n```\n\
+        d.rs\n```rust\nfn d() {}\n```\n",
+    )
+    .unwrap();
+
+    // An unknown answer is asked again; quitting at c.rs skips the rest
+    let mut answers = Cursor::new("y\nmaybe\nn\nq\n");
+    let mut prompts = Vec::new();
+    let mut reviewer = Reviewer::new(&mut answers, &mut prompts);
+    let report = Injector::default()
+        .inject_reviewed(&input, &repo, &mut reviewer)
+        .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(repo.join("a.rs")).unwrap(),
+        "fn a_good() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("b.rs")).unwrap(),
+        "fn b() {}\n"
+    );
+    assert!(!repo.join("c.rs").exists());
+    let reasons: Vec<_> = report.blocks.iter().map(|b| b.reason.as_deref()).collect();
+    assert_eq!(
+        reasons,
+        vec![
+            None,
+            Some("rejected during review"),
+            Some("review stopped"),
+            Some("review stopped"),
+        ]
+    );
+
+    let prompts = String::from_utf8(prompts).unwrap();
+    assert!(prompts.contains("-fn a() {}\n+fn a_good() {}"));
+    assert!(prompts.contains("--- /dev/null\n+++ c.rs"));
+    assert!(prompts.contains("Unknown answer \"maybe\""));
+}
+
+#[cfg(unix)]
+#[test]
+fn inject_reviewed_uses_edited_content() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(&input, "lib.rs\n```rust\nfn model() {}\n```\n").unwrap();
+
+    let mut answers = Cursor::new("e\n");
+    let mut prompts = Vec::new();
+    let mut reviewer = Reviewer::new(&mut answers, &mut prompts);
+    reviewer.editor = Some("sed -i s/model/edited/".to_string());
+    let report = Injector::default()
+        .inject_reviewed(&input, &repo, &mut reviewer)
+        .unwrap();
+
+    assert_eq!(report.blocks[0].action, BlockAction::Created);
+    assert_eq!(
+        fs::read_to_string(repo.join("lib.rs")).unwrap(),
+        "fn edited() {}\n"
+    );
+}
+
+#[test]
+fn inject_commits_exactly_the_touched_paths_on_a_new_branch() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("a.rs"), "fn a() {}\n").unwrap();
+    fs::write(repo.join("b.rs"), "fn b() {}\n").unwrap();
+    fs::write(repo.join("c.rs"), "fn c() {}\n").unwrap();
+    init_git_repo(&repo);
+    // Unrelated local work that must stay out of the commit
+    fs::write(repo.join("notes.txt"), "todo\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "a.rs\n```rust\nfn a_new() {}\n```\n\
+        new.rs\n```rust\nfn new() {}\n```\n\
+        DELETE b.rs\n\
+        RENAME c.rs -> d.rs\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        git: Some(GitOptions {
+            branch: Some("model-change".to_string()),
+            commit_message: Some("Apply model change".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let report = injector.inject(&input, &repo).unwrap();
+
+    assert!(report.commit.is_some());
+    assert_eq!(
+        git(&repo, &["branch", "--show-current"]).trim(),
+        "model-change"
+    );
+    assert_eq!(
+        git(&repo, &["log", "-1", "--format=%s"]).trim(),
+        "Apply model change"
+    );
+    let changed = git(
+        &repo,
+        &["show", "--name-status", "--no-renames", "--format=", "HEAD"],
+    );
+    let mut changed: Vec<_> = changed.lines().collect();
+    changed.sort();
+    assert_eq!(
+        changed,
+        vec!["A\td.rs", "A\tnew.rs", "D\tb.rs", "D\tc.rs", "M\ta.rs"]
+    );
+    assert_eq!(
+        git(&repo, &["status", "--porcelain"]).trim(),
+        "?? notes.txt"
+    );
+}
+
+#[test]
+fn inject_skips_files_with_uncommitted_changes() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("a.rs"), "fn a() {}\n").unwrap();
+    init_git_repo(&repo);
+    fs::write(repo.join("a.rs"), "fn a_local() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(&input, "a.rs\n```rust\nfn a_model() {}\n```\n").unwrap();
+    let mut injector = Injector {
+        git: Some(GitOptions {
+            commit_message: Some("Apply model change".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let report = injector.inject(&input, &repo).unwrap();
+
+    assert_eq!(
+        report.blocks[0].reason.as_deref(),
+        Some("uncommitted changes")
+    );
+    assert_eq!(report.commit, None);
+    assert_eq!(
+        fs::read_to_string(repo.join("a.rs")).unwrap(),
+        "fn a_local() {}\n"
+    );
+
+    injector.force = true;
+    let report = injector.inject(&input, &repo).unwrap();
+    assert_eq!(report.blocks[0].action, BlockAction::Modified);
+    assert!(report.commit.is_some());
+    assert_eq!(git(&repo, &["show", "HEAD:a.rs"]), "fn a_model() {}\n");
+}
+
+#[test]
+fn inject_into_new_worktree() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("src/lib.rs"), "fn old() {}\n").unwrap();
+    init_git_repo(&repo);
+
+    let input = dir.path().join("response.in");
+    fs::write(&input, "lib.rs\n```rust\nfn new() {}\n```\n").unwrap();
+    let worktree = dir.path().join("worktree");
+    let injector = Injector {
+        git: Some(GitOptions {
+            branch: Some("model-change".to_string()),
+            worktree: Some(worktree.clone()),
+            commit_message: Some("Apply model change".to_string()),
+        }),
+        ..Default::default()
+    };
+    // Injecting into a subdirectory keeps targeting it inside the worktree
+    injector.inject(&input, &repo.join("src")).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(repo.join("src/lib.rs")).unwrap(),
+        "fn old() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(worktree.join("src/lib.rs")).unwrap(),
+        "fn new() {}\n"
+    );
+    assert_eq!(
+        git(&repo, &["show", "model-change:src/lib.rs"]),
+        "fn new() {}\n"
+    );
+}
+
+#[test]
+fn inject_into_new_worktree_given_relative_to_working_directory() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("a.rs"), "fn old() {}\n").unwrap();
+    init_git_repo(&repo);
+
+    let input = dir.path().join("response.in");
+    fs::write(&input, "a.rs\n```rust\nfn new() {}\n```\n").unwrap();
+    // The same directory as `dir/worktree`, written relative to the working directory
+    let cwd = std::env::current_dir().unwrap();
+    let mut worktree = PathBuf::new();
+    for _ in cwd.ancestors().skip(1) {
+        worktree.push("..");
+    }
+    worktree.push(dir.path().join("worktree").strip_prefix("/").unwrap());
+    assert!(worktree.is_relative());
+    let injector = Injector {
+        git: Some(GitOptions {
+            branch: Some("model-change".to_string()),
+            worktree: Some(worktree),
+            commit_message: Some("Apply model change".to_string()),
+        }),
+        ..Default::default()
+    };
+    injector.inject(&input, &repo).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("worktree/a.rs")).unwrap(),
+        "fn new() {}\n"
+    );
+    assert!(!repo.join(dir.path().strip_prefix("/").unwrap()).exists());
+    assert_eq!(
+        fs::read_to_string(repo.join("a.rs")).unwrap(),
+        "fn old() {}\n"
+    );
+}
+
+/// A 30-line Python module used by the placeholder tests
+fn long_module() -> String {
+    let mut module = String::from("import os\n\n");
+    for i in 0..9 {
+        module.push_str(&format!("def f{i}():\n    return {i}\n\n"));
+    }
+    module.push_str("def main():\n    print(f0())\n");
+    module
+}
+
+#[test]
+fn inject_refuses_lazy_placeholders_and_shrunk_files() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("a.py"), long_module()).unwrap();
+    fs::write(repo.join("b.py"), long_module()).unwrap();
+    fs::write(repo.join("c.py"), "def stub():\n    ...\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "a.py\n```python\nimport os\n\n# ... existing code here ...\n\ndef main():\n    print(f1())\n```\n\
+        b.py\n```python\ndef main():\n    print(f1())\n```\n\
+        c.py\n```python\ndef stub():\n    ...\n\ndef other():\n    return 1\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, &repo).unwrap();
+
+    assert_eq!(
+        report.blocks[0].reason.as_deref(),
+        Some("contains placeholder \"# ... existing code here ...\"")
+    );
+    assert_eq!(
+        report.blocks[1].reason.as_deref(),
+        Some("93% shorter than the original")
+    );
+    // `...` already present in the original is not a placeholder
+    assert_eq!(report.blocks[2].action, BlockAction::Modified);
+    assert_eq!(
+        fs::read_to_string(repo.join("a.py")).unwrap(),
+        long_module()
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("b.py")).unwrap(),
+        long_module()
+    );
+
+    let injector = Injector {
+        placeholders: PlaceholderPolicy {
+            allow: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let report = injector.inject(&input, &repo).unwrap();
+    assert_eq!(report.count(BlockAction::Modified), 2);
+}
+
+#[test]
+fn inject_accepts_ellipsis_bodies_and_ordinary_comments() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("shapes.py"), "class Shape:\n    pass\n").unwrap();
+    fs::write(repo.join("c.rs"), "fn c() {}\n").unwrap();
+
+    let protocol = "from typing import Protocol\n\n\
+        class Reader(Protocol):\n    def read(self) -> bytes:\n        ...\n\n\
+        \x20   def close(self) -> None:\n        \"\"\"Close it.\"\"\"\n        ...\n";
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        format!(
+            "proto.py\n```python\n{protocol}```\n\
+            a.py\n```python\n# Other functions below handle parsing.\ndef parse():\n    return 1\n```\n\
+            b.rs\n```rust\n// Same logic as the previous implementation, but iterative.\nfn b() {{}}\n```\n\
+            shapes.py\n```python\nclass Shape:\n    def area(self) -> float:\n        ...\n```\n\
+            c.rs\n```rust\n// Other functions below handle parsing.\nfn c() {{}}\n```\n"
+        ),
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, &repo).unwrap();
+
+    let actions: Vec<_> = report.blocks.iter().map(|b| b.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            BlockAction::Created,
+            BlockAction::Created,
+            BlockAction::Created,
+            BlockAction::Modified,
+            BlockAction::Modified,
+        ]
+    );
+    assert_eq!(fs::read_to_string(repo.join("proto.py")).unwrap(), protocol);
+}
+
+#[test]
+fn inject_splices_elided_regions_back_in() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("a.py"), long_module()).unwrap();
+    fs::write(repo.join("b.py"), "x = 1\n\nx = 1\n\nprint(x)\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "a.py\n```python\nimport os\n\nimport sys\n\n# ... rest of the code unchanged ...\n\ndef main():\n    print(f1())\n```\n\
+        b.py\n```python\nx = 1\n# ...\nprint(x + 1)\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        placeholders: PlaceholderPolicy {
+            splice: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let report = injector.inject(&input, &repo).unwrap();
+
+    let expected = long_module()
+        .replace("import os\n", "import os\n\nimport sys\n")
+        .replace("print(f0())", "print(f1())");
+    assert_eq!(fs::read_to_string(repo.join("a.py")).unwrap(), expected);
+    assert_eq!(
+        report.blocks[0].reason.as_deref(),
+        Some("spliced elided regions from the original")
+    );
+    // `x = 1` occurs twice, so the placeholder's region is ambiguous
+    assert_eq!(report.blocks[1].action, BlockAction::Skipped);
+}
+
+#[test]
+fn inject_replaces_a_single_python_symbol() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(
+        repo.join("models.py"),
+        "import os\n\n\nclass User:\n    name = \"x\"\n\n    @property\n    def label(self):\n        return self.name\n\n    def save(self):\n        pass  # keep me untouched?\n\n\ndef helper():\n    return  1\n",
+    )
+    .unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "models.py::User.save\n```python\ndef save(self):\n    os.makedirs(\"out\")\n    return True\n```\n\
+        `models.py::User.label`\n```python\n    @property\n    def label(self):\n        return self.name.upper()\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, repo).unwrap();
+
+    assert_eq!(
+        report.blocks[0].target_path.as_deref(),
+        Some("models.py::User.save")
+    );
+    assert_eq!(report.count(BlockAction::Modified), 2);
+    // Everything outside the two methods, including odd spacing, is kept
+    assert_eq!(
+        fs::read_to_string(repo.join("models.py")).unwrap(),
+        "import os\n\n\nclass User:\n    name = \"x\"\n\n    @property\n    def label(self):\n        return self.name.upper()\n\n    def save(self):\n        os.makedirs(\"out\")\n        return True\n\n\ndef helper():\n    return  1\n"
+    );
+}
+
+#[test]
+fn inject_replaces_a_single_rust_symbol() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(
+        repo.join("src/run.rs"),
+        "struct Generator;\n\nimpl Generator {\n    /// Runs it.\n    pub fn run(&self) -> u8 {\n        1\n    }\n}\n\nimpl std::fmt::Display for Generator {\n    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {\n        write!(f, \"g\")\n    }\n}\n\nfn   untouched() {}\n",
+    )
+    .unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "src/run.rs::Generator::run\n```rust\n/// Runs it twice.\npub fn run(&self) -> u8 {\n    2\n}\n```\n\
+        `src/run.rs::impl std::fmt::Display for Generator`\n```rust\nimpl std::fmt::Display for Generator {\n    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {\n        write!(f, \"gen\")\n    }\n}\n```\n\
+        src/run.rs::missing\n```rust\nfn missing() {}\n```\n\
+        src/run.rs::Generator\n```rust\nstruct Generator(u8);\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, repo).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(repo.join("src/run.rs")).unwrap(),
+        "struct Generator(u8);\n\nimpl Generator {\n    /// Runs it twice.\n    pub fn run(&self) -> u8 {\n        2\n    }\n}\n\nimpl std::fmt::Display for Generator {\n    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {\n        write!(f, \"gen\")\n    }\n}\n\nfn   untouched() {}\n"
+    );
+    assert_eq!(
+        report.blocks[2].reason.as_deref(),
+        Some("symbol `missing` not found")
+    );
+}
+
+#[test]
+fn strict_parsing_only_accepts_explicit_path_markers() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src")).unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "Done.\n```rust\nfn prose() {}\n```\n\
+        ### `src/a.rs`\n```rust\nfn a() {}\n```\n\
+        ```rust src/b.rs\nfn b() {}\n```\n\
+        `src/c.rs`\n```rust\nfn c() {}\n```\n\
+        **`src/d.rs`**\n```python\nfn d() {}\n```\n\
+        ### `Here:`\n```rust\nfn here() {}\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        strict_paths: true,
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    assert!(!repo.join("Done.").exists());
+    assert!(repo.join("src/a.rs").exists());
+    assert!(repo.join("src/b.rs").exists());
+    assert!(!repo.join("src/c.rs").exists());
+    assert!(!repo.join("src/d.rs").exists());
+    assert!(!repo.join("Here:").exists());
+    let warnings: Vec<_> = report
+        .warnings
+        .iter()
+        .map(|w| (w.line, w.text.as_str()))
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![(1, "Done."), (12, "`src/c.rs`"), (20, "### `Here:`")]
+    );
+    assert_eq!(
+        report.blocks[4].reason.as_deref(),
+        Some("fence language `python` does not match the file extension")
+    );
+    assert!(report.has_skipped());
+
+    // Without strict mode, prose is still taken as a path
+    Injector::default().inject(&input, repo).unwrap();
+    assert!(repo.join("Done.").exists());
+}
+
+#[test]
+fn strict_parsing_only_accepts_explicit_directives() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    for file in ["x", "a", "gone.rs", "old.rs"] {
+        fs::write(repo.join(file), "keep\n").unwrap();
+    }
+
+    let input = dir.path().join("input.in");
+    fs::write(
+        &input,
+        "DELETE x\n\
+        RENAME a -> b\n\
+        ### DELETE gone.rs\n\
+        ### DELETE `gone.rs`\n\
+        ### RENAME `old.rs` -> `new.rs`\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        strict_paths: true,
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    let actions: Vec<_> = report.blocks.iter().map(|b| b.action).collect();
+    assert_eq!(actions, vec![BlockAction::Deleted, BlockAction::Renamed]);
+    let warnings: Vec<_> = report
+        .warnings
+        .iter()
+        .map(|w| (w.line, w.text.as_str()))
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![
+            (1, "DELETE x"),
+            (2, "RENAME a -> b"),
+            (3, "### DELETE gone.rs")
+        ]
+    );
+    assert!(repo.join("x").exists());
+    assert!(repo.join("a").exists());
+    assert!(!repo.join("b").exists());
+    assert!(!repo.join("gone.rs").exists());
+    assert!(repo.join("new.rs").exists());
+}
+
+#[test]
+fn inject_resolves_mangled_paths_to_existing_files() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("myrepo");
+    fs::create_dir_all(repo.join("src/prmpt")).unwrap();
+    fs::create_dir_all(repo.join("tests")).unwrap();
+    for file in [
+        "src/lib.rs",
+        "src/main.rs",
+        "src/prmpt/run.rs",
+        "src/prmpt/mod.rs",
+        "src/mod.rs",
+    ] {
+        fs::write(repo.join(file), "// old\n").unwrap();
+    }
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "myrepo/src/lib.rs\n```rust\n// lib\n```\n\
+        ./src/./main.rs\n```rust\n// main\n```\n\
+        /home/someone/code/myrepo/src/prmpt/run.rs\n```rust\n// run\n```\n\
+        src/utils/run.rs\n```rust\n// run again\n```\n\
+        mod.rs\n```rust\n// ambiguous\n```\n\
+        tests/lib.rs\n```rust\n// new test\n```\n\
+        config.rs\n```rust\n// new module\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, &repo).unwrap();
+
+    let resolved: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| {
+            b.resolved_path
+                .as_ref()
+                .map(|p| p.to_string_lossy().into_owned())
+        })
+        .collect();
+    assert_eq!(
+        resolved,
+        vec![
+            Some("src/lib.rs".to_string()),
+            Some("src/main.rs".to_string()),
+            Some("src/prmpt/run.rs".to_string()),
+            Some("src/utils/run.rs".to_string()),
+            None,
+            Some("tests/lib.rs".to_string()),
+            Some("config.rs".to_string()),
+        ]
+    );
+    assert_eq!(
+        report.blocks[0].target_path.as_deref(),
+        Some("myrepo/src/lib.rs")
+    );
+    assert_eq!(
+        report.blocks[4].reason.as_deref(),
+        Some("ambiguous path, matches src/mod.rs, src/prmpt/mod.rs")
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("src/prmpt/run.rs")).unwrap(),
+        "// run\n"
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("src/utils/run.rs")).unwrap(),
+        "// run again\n"
+    );
+    assert!(!repo.join("myrepo").exists());
+}
+
+#[test]
+fn inject_creates_files_in_new_directories_despite_matching_file_names() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(repo.join("pkg")).unwrap();
+    fs::write(repo.join("pkg/utils.py"), "OLD = 1\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "newpkg/utils.py\n```python\nNEW = 1\n```\n\
+        newpkg/sub/utils.py\n```python\nDEEP = 1\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, &repo).unwrap();
+
+    let resolved: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| b.resolved_path.clone())
+        .collect();
+    assert_eq!(
+        resolved,
+        vec![
+            Some("newpkg/utils.py".into()),
+            Some("newpkg/sub/utils.py".into())
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("pkg/utils.py")).unwrap(),
+        "OLD = 1\n"
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("newpkg/utils.py")).unwrap(),
+        "NEW = 1\n"
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("newpkg/sub/utils.py")).unwrap(),
+        "DEEP = 1\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn inject_rolls_back_when_verify_fails() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("a.sh"), "echo old\n").unwrap();
+    fs::set_permissions(repo.join("a.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(repo.join("gone.rs"), "fn gone() {}\n").unwrap();
+    fs::write(repo.join("moved.rs"), "fn moved() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "a.sh\n```sh\necho new\n```\n\
+        new/b.rs\n```rust\nfn b() {}\n```\n\
+        DELETE gone.rs\n\
+        RENAME moved.rs -> renamed.rs\n",
+    )
+    .unwrap();
+    let followup = dir.path().join("followup.md");
+    let injector = Injector {
+        verify: Some("echo checking && grep -q never a.sh".to_string()),
+        followup: Some(followup.clone()),
+        ..Default::default()
+    };
+    let report = injector.inject(&input, &repo).unwrap();
+
+    let verification = report.verification.as_ref().unwrap();
+    assert_eq!(verification.exit_code, Some(1));
+    assert_eq!(verification.stdout, "checking\n");
+    assert!(verification.rolled_back);
+    assert_eq!(fs::read_to_string(repo.join("a.sh")).unwrap(), "echo old\n");
+    assert_eq!(
+        fs::metadata(repo.join("a.sh"))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777,
+        0o755
+    );
+    assert!(!repo.join("new").exists());
+    assert_eq!(
+        fs::read_to_string(repo.join("gone.rs")).unwrap(),
+        "fn gone() {}\n"
+    );
+    assert!(repo.join("moved.rs").exists());
+    assert!(!repo.join("renamed.rs").exists());
+
+    let prompt = fs::read_to_string(&followup).unwrap();
+    assert!(prompt.contains("`echo checking && grep -q never a.sh` failed with exit code 1"));
+    assert!(prompt.contains("```\nchecking\n```"));
+    assert!(prompt.contains("```a.sh\necho new\n```"));
+    assert!(prompt.contains("```new/b.rs\nfn b() {}\n```"));
+    assert!(prompt.contains("DELETE gone.rs"));
+}
+
+#[cfg(unix)]
+#[test]
+fn inject_reports_failed_blocks_and_still_rolls_back() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("src/lib.rs"), "fn old() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "src/lib.rs\n```rust\nfn new() {}\n```\n\
+        src/lib.rs/extra.rs\n```rust\nfn extra() {}\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        verify: Some("false".to_string()),
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    assert_eq!(report.blocks[0].action, BlockAction::Modified);
+    assert_eq!(report.blocks[1].action, BlockAction::Skipped);
+    assert!(report.blocks[1]
+        .reason
+        .as_deref()
+        .unwrap()
+        .starts_with("Failed to create parent directory"));
+    assert!(report.verification.as_ref().unwrap().rolled_back);
+    assert_eq!(
+        fs::read_to_string(repo.join("src/lib.rs")).unwrap(),
+        "fn old() {}\n"
+    );
+}
+
+#[test]
+fn inject_reports_blocks_that_fail_to_apply() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("src/lib.rs"), "fn old() {}\n").unwrap();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "src/lib.rs/extra.rs\n```rust\nfn extra() {}\n```\n\
+        src/lib.rs\n```rust\nfn new() {}\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, repo).unwrap();
+
+    assert_eq!(report.blocks[0].action, BlockAction::Skipped);
+    assert!(report.blocks[0]
+        .reason
+        .as_deref()
+        .unwrap()
+        .starts_with("Failed to create parent directory"));
+    assert_eq!(report.blocks[1].action, BlockAction::Modified);
+    assert_eq!(
+        fs::read_to_string(repo.join("src/lib.rs")).unwrap(),
+        "fn new() {}\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn inject_keeps_changes_when_verify_passes() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(repo.join("a.rs"), "fn old() {}\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(&input, "a.rs\n```rust\nfn new() {}\n```\n").unwrap();
+    let injector = Injector {
+        verify: Some("grep -q new a.rs".to_string()),
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    assert!(report.verification.as_ref().unwrap().success());
+    assert!(!report.verification.as_ref().unwrap().rolled_back);
+    assert_eq!(
+        fs::read_to_string(repo.join("a.rs")).unwrap(),
+        "fn new() {}\n"
+    );
+    assert!(report.to_string().contains("verify ok: grep -q new a.rs"));
+}
+
+#[test]
+fn parse_response_exposes_blocks_and_markers() {
+    let response = "\
+Here you go:
+
+```rust src/lib.rs
+pub fn a() {}
+```
+
+### `src/util.py::helper`
+```python
+def helper():
+    return 1
+```
+
+**`docs/notes.md`**
+```
+notes
+```
+
+DELETE old.rs
+RENAME `a.rs` -> `b.rs`
+";
+    let blocks = parse_response(response, &ParseOptions::default());
+    assert_eq!(blocks.len(), 5);
+
+    assert_eq!(blocks[0].target_path, "src/lib.rs");
+    assert_eq!(blocks[0].language.as_deref(), Some("rust"));
+    assert_eq!(blocks[0].content, "pub fn a() {}");
+    assert_eq!((blocks[0].start_line, blocks[0].end_line), (3, 5));
+    assert_eq!(blocks[0].marker, PathMarker::Fence);
+    assert_eq!(blocks[0].operation, BlockOperation::Write);
+
+    assert_eq!(blocks[1].target_path, "src/util.py");
+    assert_eq!(blocks[1].symbol.as_deref(), Some("helper"));
+    assert_eq!(blocks[1].language.as_deref(), Some("python"));
+    assert_eq!((blocks[1].start_line, blocks[1].end_line), (7, 11));
+    assert_eq!(blocks[1].marker, PathMarker::Heading);
+
+    assert_eq!(blocks[2].target_path, "docs/notes.md");
+    assert_eq!(blocks[2].language, None);
+    assert_eq!(blocks[2].marker, PathMarker::Bold);
+
+    assert_eq!(blocks[3].operation, BlockOperation::Delete);
+    assert_eq!(blocks[3].marker, PathMarker::Directive);
+    assert_eq!(
+        blocks[4].operation,
+        BlockOperation::Rename("b.rs".to_string())
+    );
+
+    // Strict mode ignores the bare path line, as the CLI does
+    let strict = ParseOptions {
+        strict_paths: true,
+        ..Default::default()
+    };
+    let blocks = parse_response("src/a.rs\n```\nx\n```\n", &strict);
+    assert!(blocks.is_empty());
+    let blocks = parse_response("src/a.rs\n```\nx\n```\n", &ParseOptions::default());
+    assert_eq!(blocks[0].marker, PathMarker::Bare);
+}
+
+#[test]
+fn inject_rejects_unsafe_targets_before_touching_the_file_system() {
+    let root = tempdir().unwrap();
+    let repo = root.path().join("repo");
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    fs::write(repo.join(".git/config"), "[core]\n").unwrap();
+    fs::write(repo.join("keep.rs"), "fn keep() {}\n").unwrap();
+
+    let input = root.path().join("response.in");
+    fs::write(
+        &input,
+        "../../evil/x.rs\n```rust\nfn evil() {}\n```\n\
+        ### `/etc/prmpt_absolute.rs`\n```rust\nfn absolute() {}\n```\n\
+        ### `.git/config`\n```\n[core]\nbare = true\n```\n\
+        ### `src/../../sibling/y.rs`\n```rust\nfn sibling() {}\n```\n\
+        RENAME keep.rs -> .git/hooks/pre-commit\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, &repo).unwrap();
+
+    let reasons: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| (b.action, b.reason.as_deref().unwrap_or_default()))
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            (BlockAction::Skipped, "path contains `..`"),
+            (BlockAction::Skipped, "absolute path"),
+            (BlockAction::Skipped, "path points into .git"),
+            (BlockAction::Skipped, "path contains `..`"),
+            (
+                BlockAction::Skipped,
+                "destination rejected: path points into .git"
+            ),
+        ]
+    );
+    // No directories were created for the rejected targets
+    assert!(!root.path().join("evil").exists());
+    assert!(!repo.join("src").exists());
+    assert!(!root.path().join("sibling").exists());
+    assert!(!Path::new("/etc/prmpt_absolute.rs").exists());
+    assert_eq!(
+        fs::read_to_string(repo.join(".git/config")).unwrap(),
+        "[core]\n"
+    );
+    assert!(repo.join("keep.rs").exists());
+}
+
+#[test]
+fn inject_creates_no_directories_for_rejected_blocks() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+
+    let input = dir.path().join("response.in");
+    fs::write(
+        &input,
+        "new/deep/dir/lib.rs\n```rust\nfn broken( {\n```\n\
+        other/big.rs\n```rust\nfn big() { /* longer than the limit */ }\n```\n\
+        ok/lib.rs\n```rust\nfn ok() {}\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        policy: WritePolicy {
+            max_file_size: Some(20),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    let actions: Vec<_> = report.blocks.iter().map(|b| b.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            BlockAction::Skipped,
+            BlockAction::Skipped,
+            BlockAction::Created
+        ]
+    );
+    assert!(!repo.join("new").exists());
+    assert!(!repo.join("other").exists());
+    assert!(repo.join("ok/lib.rs").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn inject_rejects_targets_through_symlinked_directories() {
+    let root = tempdir().unwrap();
+    let repo = root.path().join("repo");
+    let outside = root.path().join("outside");
+    fs::create_dir_all(&repo).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(&outside, repo.join("link")).unwrap();
+
+    let input = root.path().join("response.in");
+    fs::write(&input, "link/nested/x.rs\n```rust\nfn x() {}\n```\n").unwrap();
+    let report = Injector::default().inject(&input, &repo).unwrap();
+
+    assert_eq!(report.blocks[0].action, BlockAction::Skipped);
+    assert_eq!(
+        report.blocks[0].reason.as_deref(),
+        Some("path goes through a symlink: link")
+    );
+    assert!(!outside.join("nested").exists());
+}
+
+#[test]
+fn inject_policy_denies_lockfiles_and_binaries_by_default() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(repo.join("Cargo.lock"), "# lock\n").unwrap();
+    fs::write(repo.join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0, 1]).unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "### `Cargo.lock`\n```\n# edited\n```\n\
+        ### `logo.png`\n```\nnot an image\n```\n\
+        ### `prmpt.yaml`\n```yaml\nbase: {}\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, repo).unwrap();
+
+    let reasons: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| b.reason.as_deref().unwrap_or_default())
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            "denied by policy (**/Cargo.lock)",
+            "refusing to overwrite a binary file",
+            "denied by policy (prmpt.yaml)",
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("Cargo.lock")).unwrap(),
+        "# lock\n"
+    );
+    assert!(!repo.join("prmpt.yaml").exists());
+}
+
+#[test]
+fn inject_policy_from_target_repository_adds_to_default_deny() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("secrets")).unwrap();
+    fs::write(repo.join("Cargo.lock"), "# lock\n").unwrap();
+    fs::write(repo.join("secrets/key.txt"), "key\n").unwrap();
+    fs::write(
+        repo.join("prmpt.yaml"),
+        "inject:\n  policy:\n    deny: [\"secrets/**\"]\n",
+    )
+    .unwrap();
+
+    let input = dir.path().join("input.in");
+    fs::write(
+        &input,
+        "### `Cargo.lock`\n```\n# edited\n```\n\
+        ### `secrets/key.txt`\n```\nleaked\n```\n",
+    )
+    .unwrap();
+    let policy = load_config_from(repo).unwrap()[DEFAULT_CONFIG_KEY]
+        .inject
+        .as_ref()
+        .and_then(|inject| inject.policy.clone())
+        .unwrap();
+    let mut injector = Injector {
+        policy,
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+    let reasons: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| b.reason.as_deref().unwrap_or_default())
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            "denied by policy (**/Cargo.lock)",
+            "denied by policy (secrets/**)"
+        ]
+    );
+
+    injector.policy.replace_default_deny = Some(true);
+    let report = injector.inject(&input, repo).unwrap();
+    assert_eq!(report.blocks[0].action, BlockAction::Modified);
+    assert_eq!(report.blocks[1].action, BlockAction::Skipped);
+    assert_eq!(
+        fs::read_to_string(repo.join("Cargo.lock")).unwrap(),
+        "# edited\n"
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("secrets/key.txt")).unwrap(),
+        "key\n"
+    );
+}
+
+#[test]
+fn inject_policy_limits_paths_creation_and_size() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("src/lib.rs"), "fn a() {}\n").unwrap();
+    fs::write(repo.join("src/big.rs"), "fn b() {}\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "src/lib.rs\n```rust\nfn a2() {}\n```\n\
+        src/new.rs\n```rust\nfn n() {}\n```\n\
+        src/deep/new.rs\n```rust\nfn d() {}\n```\n\
+        README.md\n```\nhi\n```\n\
+        src/big.rs\n```rust\nfn b() { /* far too long for the limit */ }\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        policy: WritePolicy {
+            allow: Some(vec!["src/**".to_string()]),
+            create_files: Some(false),
+            create_dirs: Some(false),
+            max_file_size: Some(20),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    let outcome: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| (b.action, b.reason.as_deref().unwrap_or_default()))
+        .collect();
+    assert_eq!(
+        outcome,
+        vec![
+            (BlockAction::Modified, ""),
+            (BlockAction::Skipped, "policy forbids creating files"),
+            (BlockAction::Skipped, "policy forbids creating directories"),
+            (BlockAction::Skipped, "not in the policy's allow list"),
+            (
+                BlockAction::Skipped,
+                "44 bytes, more than the policy's max_file_size (20)"
+            ),
+        ]
+    );
+    assert!(!repo.join("src/deep").exists());
+    assert_eq!(
+        fs::read_to_string(repo.join("src/big.rs")).unwrap(),
+        "fn b() {}\n"
+    );
+
+    // Too many blocks refuses the whole response before anything is written
+    let injector = Injector {
+        policy: WritePolicy {
+            max_blocks: Some(2),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let error = injector.inject(&input, repo).unwrap_err();
+    assert!(error.to_string().contains("response has 5 blocks"));
+    assert!(!repo.join("src/new.rs").exists());
+}
+
+#[test]
+fn inject_policy_globs_do_not_cross_directories() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::create_dir_all(repo.join("src/a/b")).unwrap();
+    fs::write(repo.join("src/lib.rs"), "fn a() {}\n").unwrap();
+    fs::write(repo.join("src/a/b/c.rs"), "fn c() {}\n").unwrap();
+
+    let input = dir.path().join("input.in");
+    fs::write(
+        &input,
+        "src/lib.rs\n```rust\nfn a2() {}\n```\n\
+        src/a/b/c.rs\n```rust\nfn c2() {}\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        policy: WritePolicy {
+            allow: Some(vec!["src/*".to_string()]),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    assert_eq!(report.blocks[0].action, BlockAction::Modified);
+    assert_eq!(
+        report.blocks[1].reason.as_deref(),
+        Some("not in the policy's allow list")
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("src/a/b/c.rs")).unwrap(),
+        "fn c() {}\n"
+    );
+}
+
+#[test]
+fn parse_response_reads_xml_tagged_blocks() {
+    let response = r#"I'll update two files.
+
+<file path="src/lib.rs">
+pub fn cmp(a: u8, b: u8) -> bool { a &lt; b &amp;&amp; b &gt; 0 }
+</file>
+
+```xml
+<file path='src/raw.rs'><![CDATA[
+fn raw() -> &'static str { "</file> &lt;" }
+]]></file>
+```
+
+<edit path="src/main.rs">
+  <search>
+fn main() {}
+  </search>
+  <replace>fn main() { println!("&#x48;i"); }</replace>
+</edit>
+
+<file path="broken.rs">
+never closed
+"#;
+    let parsed = prmpt::parse_response_with_diagnostics(response, &ParseOptions::default());
+    let blocks = parsed.blocks;
+    assert_eq!(blocks.len(), 3);
+
+    assert_eq!(blocks[0].target_path, "src/lib.rs");
+    assert_eq!(
+        blocks[0].content,
+        "pub fn cmp(a: u8, b: u8) -> bool { a < b && b > 0 }"
+    );
+    assert_eq!((blocks[0].start_line, blocks[0].end_line), (3, 5));
+    assert_eq!(blocks[0].marker, PathMarker::XmlTag);
+
+    // CDATA is taken verbatim, even when it contains the closing tag
+    assert_eq!(blocks[1].target_path, "src/raw.rs");
+    assert_eq!(
+        blocks[1].content,
+        "fn raw() -> &'static str { \"</file> &lt;\" }"
+    );
+    assert_eq!((blocks[1].start_line, blocks[1].end_line), (8, 10));
+
+    assert_eq!(blocks[2].target_path, "src/main.rs");
+    assert_eq!(
+        blocks[2].operation,
+        BlockOperation::Edit(vec![prmpt::SearchReplace {
+            search: "fn main() {}".to_string(),
+            replace: "fn main() { println!(\"Hi\"); }".to_string(),
+        }])
+    );
+
+    assert_eq!(parsed.skipped.len(), 1);
+    assert_eq!(parsed.skipped[0].reason.as_deref(), Some("missing </file>"));
+}
+
+#[test]
+fn inject_applies_xml_files_and_edits() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(
+        repo.join("main.rs"),
+        "fn main() {\r\n    old();\r\n}\r\n\r\nfn old() {}\r\n",
+    )
+    .unwrap();
+    fs::write(repo.join("twice.rs"), "x();\nx();\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "<file path=\"new.rs\">\nfn new() {}\n</file>\n\
+        <edit path=\"main.rs\">\n<search>\n    old();\n}\n</search>\n\
+        <replace>\n    new();\n}\n</replace>\n</edit>\n\
+        <edit path=\"twice.rs\"><search>x();</search><replace>y();</replace></edit>\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, repo).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(repo.join("new.rs")).unwrap(),
+        "fn new() {}\n"
+    );
+    // The edit matches and keeps the file's CRLF line endings
+    assert_eq!(
+        fs::read_to_string(repo.join("main.rs")).unwrap(),
+        "fn main() {\r\n    new();\r\n}\r\n\r\nfn old() {}\r\n"
+    );
+    assert_eq!(report.blocks[2].action, BlockAction::Skipped);
+    assert_eq!(
+        report.blocks[2].reason.as_deref(),
+        Some("search text 1 matches 2 times")
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("twice.rs")).unwrap(),
+        "x();\nx();\n"
+    );
+}
+
+#[test]
+fn inject_applies_json_file_operations() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(repo.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+    fs::write(repo.join("old.rs"), "fn old() {}\n").unwrap();
+    fs::write(repo.join("gone.rs"), "fn gone() {}\n").unwrap();
+
+    let input = repo.join("response.json");
+    fs::write(
+        &input,
+        r#"```json
+{"files": [
+  {"path": "new.rs", "content": "fn new() {}\n"},
+  {"op": "edit", "path": "lib.rs", "edits": [{"search": "fn b() {}", "replace": "fn c() {}"}]},
+  {"op": "delete", "path": "gone.rs"},
+  {"name": "rename_file", "arguments": "{\"path\": \"old.rs\", \"to\": \"renamed.rs\"}"},
+  {"op": "write", "content": "no path"},
+  {"op": "chmod", "path": "lib.rs"}
+]}
+```"#,
+    )
+    .unwrap();
+    let injector = Injector {
+        format: ResponseFormat::Json,
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+
+    let outcome: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| {
+            (
+                b.start_line,
+                b.action,
+                b.reason.as_deref().unwrap_or_default(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outcome,
+        vec![
+            (1, BlockAction::Created, ""),
+            (2, BlockAction::Modified, ""),
+            (3, BlockAction::Deleted, ""),
+            (4, BlockAction::Renamed, ""),
+            (5, BlockAction::Skipped, "operation without a path"),
+            (6, BlockAction::Skipped, "unknown operation `chmod`"),
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("new.rs")).unwrap(),
+        "fn new() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("lib.rs")).unwrap(),
+        "fn a() {}\nfn c() {}\n"
+    );
+    assert!(!repo.join("gone.rs").exists());
+    assert!(repo.join("renamed.rs").exists());
+
+    // Tool calls in the OpenAI shape, as a bare array
+    let input = repo.join("tool_calls.json");
+    fs::write(
+        &input,
+        r#"[{"type": "function", "function": {"name": "delete_file", "arguments": {"file_path": "renamed.rs"}}}]"#,
+    )
+    .unwrap();
+    let report = injector.inject(&input, repo).unwrap();
+    assert_eq!(report.blocks[0].action, BlockAction::Deleted);
+
+    fs::write(&input, "not json").unwrap();
+    assert!(injector.inject(&input, repo).is_err());
+}
+
+#[test]
+fn inject_response_from_chat_transcripts() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+
+    // OpenAI/Anthropic style: content as a string or as text parts
+    let messages = prmpt::read_transcript(
+        r#"{"messages": [
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": "Write a.rs"},
+            {"role": "assistant", "content": [{"type": "text", "text": "a.rs\n```rust\nfn a() {}\n```"}]},
+            {"role": "user", "content": "Thanks"}
+        ]}"#,
+    )
+    .unwrap();
+    assert_eq!(messages.len(), 4);
+    let response = prmpt::select_message(&messages, None).unwrap();
+    let report = Injector::default().inject_response(response, repo).unwrap();
+    assert_eq!(report.blocks[0].action, BlockAction::Created);
+    assert_eq!(
+        fs::read_to_string(repo.join("a.rs")).unwrap(),
+        "fn a() {}\n"
+    );
+    assert_eq!(
+        prmpt::select_message(&messages, Some(1)).unwrap(),
+        "Write a.rs"
+    );
+    assert!(prmpt::select_message(&messages, Some(9)).is_err());
+
+    // ChatGPT export: a mapping of nodes ordered by creation time
+    let messages = prmpt::read_transcript(
+        r#"{"title": "t", "mapping": {
+            "b": {"message": {"author": {"role": "assistant"}, "create_time": 2.0, "content": {"parts": ["second"]}}},
+            "root": {"message": null},
+            "a": {"message": {"author": {"role": "assistant"}, "create_time": 1.0, "content": {"parts": ["first"]}}}
+        }}"#,
+    )
+    .unwrap();
+    assert_eq!(prmpt::select_message(&messages, None).unwrap(), "second");
+
+    // `--message` counts entries without a role, as they appear in the input
+    let messages = prmpt::read_transcript(
+        r#"[
+            {"content": "no role"},
+            {"role": "user", "content": "q"},
+            {"role": "assistant", "content": "a"}
+        ]"#,
+    )
+    .unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(prmpt::select_message(&messages, Some(2)).unwrap(), "a");
+    assert_eq!(prmpt::select_message(&messages, Some(1)).unwrap(), "q");
+    assert!(prmpt::select_message(&messages, Some(0)).is_err());
+
+    // Claude export and Gemini `contents`
+    let messages = prmpt::read_transcript(
+        r#"{"chat_messages": [{"sender": "human", "text": "q"}, {"sender": "assistant", "text": "answer"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(prmpt::select_message(&messages, None).unwrap(), "answer");
+    let messages = prmpt::read_transcript(
+        r#"{"contents": [{"role": "user", "parts": [{"text": "q"}]}, {"role": "model", "parts": [{"text": "gemini"}]}]}"#,
+    )
+    .unwrap();
+    assert_eq!(prmpt::select_message(&messages, None).unwrap(), "gemini");
+
+    let messages = prmpt::read_transcript(r#"[{"role": "user", "content": "only me"}]"#).unwrap();
+    assert!(prmpt::select_message(&messages, None).is_err());
+}
+
+#[test]
+fn inject_rejects_blocks_with_syntax_errors() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    fs::write(repo.join("lib.rs"), "fn a() {}\n").unwrap();
+    fs::write(repo.join("broken.py"), "def f(:\n").unwrap();
+
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "lib.rs\n```rust\nfn a() {\n    let s = \"cut off;\n```\n\
+        config.json\n```json\n{\"a\": 1,\n```\n\
+        app.ts\n```ts\nconst x: number = 1;\n```\n\
+        settings.toml\n```toml\n[server]\nport = 80\n```\n\
+        broken.py\n```python\ndef f(:\n    pass\n```\n",
+    )
+    .unwrap();
+    let report = Injector::default().inject(&input, repo).unwrap();
+
+    let outcome: Vec<_> = report
+        .blocks
+        .iter()
+        .map(|b| (b.action, b.reason.clone().unwrap_or_default()))
+        .collect();
+    assert_eq!(outcome[0].0, BlockAction::Skipped);
+    assert!(
+        outcome[0].1.starts_with("syntax error at line 2"),
+        "{outcome:?}"
+    );
+    assert_eq!(outcome[1].0, BlockAction::Skipped);
+    assert!(outcome[1].1.starts_with("syntax error at line"));
+    assert_eq!(outcome[2].0, BlockAction::Created);
+    assert_eq!(outcome[3].0, BlockAction::Created);
+    // A file that did not parse before is not held to a higher standard
+    assert_eq!(outcome[4].0, BlockAction::Modified);
+    assert_eq!(
+        fs::read_to_string(repo.join("lib.rs")).unwrap(),
+        "fn a() {}\n"
+    );
+    assert!(!repo.join("config.json").exists());
+
+    let injector = Injector {
+        allow_syntax_errors: true,
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+    assert_eq!(report.blocks[0].action, BlockAction::Modified);
+    assert!(report.blocks[0]
+        .reason
+        .as_deref()
+        .unwrap()
+        .ends_with("(written anyway)"));
+}
+
+#[test]
+fn inject_updates_notebook_cells() {
+    let dir = tempdir().unwrap();
+    let repo = dir.path();
+    let notebook = r##"{
+ "cells": [
+  {"cell_type": "markdown", "id": "intro", "metadata": {}, "source": ["# Title"]},
+  {"cell_type": "code", "execution_count": 1, "id": "load", "metadata": {"tags": ["setup"]},
+   "outputs": [{"name": "stdout", "output_type": "stream", "text": ["loaded\n"]}],
+   "source": ["import pandas as pd\n", "df = pd.read_csv('a.csv')"]},
+  {"cell_type": "raw", "id": "raw", "metadata": {}, "source": ["keep me"]},
+  {"cell_type": "code", "execution_count": 2, "id": "plot", "metadata": {},
+   "outputs": [{"data": {"text/plain": ["<plot>"]}, "execution_count": 2, "metadata": {}, "output_type": "execute_result"}],
+   "source": ["df.plot()"]},
+  {"cell_type": "code", "execution_count": 3, "id": "old", "metadata": {}, "outputs": [], "source": ["print('bye')"]}
+ ],
+ "metadata": {"kernelspec": {"name": "python3"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}
+"##;
+    fs::write(repo.join("analysis.ipynb"), notebook).unwrap();
+
+    // As generated with outputs displayed, with the path in the fence: cell 3 is edited, cell 4 removed and a
+    // markdown cell added
+    let input = repo.join("input.in");
+    fs::write(
+        &input,
+        "```analysis.ipynb\n\
+        // Cell #0 (markdown)\n# Title\n\
+        // Cell #1 (code)\nimport pandas as pd\ndf = pd.read_csv('a.csv')\n\
+        // Cell #1 (outputs)\nloaded\n\n\
+        // Cell #3 (code)\ndf.plot(kind='bar')\n\
+        // Cell #3 (outputs)\n<plot>\n\
+        // Cell #5 (markdown)\nDone.\n\n```\n",
+    )
+    .unwrap();
+    let injector = Injector {
+        strict_paths: true,
+        ..Default::default()
+    };
+    let report = injector.inject(&input, repo).unwrap();
+    assert_eq!(report.blocks[0].action, BlockAction::Modified);
+
+    let updated = fs::read_to_string(repo.join("analysis.ipynb")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&updated).unwrap();
+    assert_eq!(json["metadata"]["kernelspec"]["name"], "python3");
+    assert_eq!(json["nbformat_minor"], 5);
+    let cells = json["cells"].as_array().unwrap();
+    let ids: Vec<_> = cells.iter().map(|c| c["id"].as_str().unwrap()).collect();
+    assert_eq!(&ids[..4], ["intro", "load", "raw", "plot"]);
+    assert_eq!(cells.len(), 5);
+
+    // Untouched cells keep their outputs and metadata
+    assert_eq!(cells[1]["execution_count"], 1);
+    assert_eq!(cells[1]["metadata"]["tags"][0], "setup");
+    assert_eq!(cells[1]["outputs"][0]["text"][0], "loaded\n");
+    assert_eq!(
+        cells[1]["source"],
+        serde_json::json!(["import pandas as pd\n", "df = pd.read_csv('a.csv')"])
+    );
+    assert_eq!(cells[2]["source"][0], "keep me");
+    // Edited cells lose their now stale outputs
+    assert_eq!(
+        cells[3]["source"],
+        serde_json::json!(["df.plot(kind='bar')"])
+    );
+    assert_eq!(cells[3]["outputs"], serde_json::json!([]));
+    assert!(cells[3]["execution_count"].is_null());
+    assert_eq!(cells[4]["cell_type"], "markdown");
+    assert_eq!(cells[4]["source"], serde_json::json!(["Done."]));
+    assert!(!updated.contains("bye"));
+
+    // Injecting the same render again changes nothing
+    let report = injector.inject(&input, repo).unwrap();
+    assert_eq!(report.blocks[0].action, BlockAction::Unchanged);
+}